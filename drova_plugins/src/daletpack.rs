@@ -0,0 +1,27 @@
+use dalet::{
+    daletpack::{deserialize, serialize},
+    types::Page,
+};
+use drova_sdk::requester::{Error, InputHandler, Metadata, Output, OutputHandler};
+
+pub struct DaletpackInput;
+
+impl InputHandler for DaletpackInput {
+    fn process_text(&self, _: String, _: &Metadata) -> Result<Page, Error> {
+        Err(Error::UnsupportedInput)
+    }
+
+    fn process_bytes(&self, b: Vec<u8>, _: &Metadata) -> Result<Page, Error> {
+        deserialize(&b).map_err(|e| Error::ParserError(format!("{e:?}")))
+    }
+}
+
+pub struct DaletpackOutput;
+
+impl OutputHandler for DaletpackOutput {
+    fn render(&self, page: &Page) -> Result<Output, Error> {
+        serialize(page.clone())
+            .map(Output::Bits)
+            .map_err(|e| Error::ParserError(format!("{e:?}")))
+    }
+}
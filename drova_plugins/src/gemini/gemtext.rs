@@ -1,125 +1,533 @@
 use dalet::types::{
-    HeadingLevel, ListStyle, Page,
+    Body, HeadingLevel, ListStyle, Page, TableRows,
     Tag::{self, *},
 };
-use drova_sdk::requester::{Error, InputHandler};
+use drova_sdk::requester::{
+    Error, InputHandler, LineInput, LineParser, Metadata, Output, OutputHandler,
+};
+
 use url::Url;
 
+use crate::utils::{ascii_table, has_block, is_blank, is_block, resolve_link};
+
 pub struct GemtextInput;
 
 impl InputHandler for GemtextInput {
-    fn process_text(&self, s: String, _: Option<&Url>) -> Result<Page, Error> {
-        let mut page: Vec<Tag> = Vec::new();
-        let mut preformatted = false;
-        let mut preformatted_text: Vec<String> = Vec::new();
+    fn process_text(&self, s: String, meta: &Metadata) -> Result<Page, Error> {
+        let mut parser = GemtextParser::new(meta.url.clone());
+        let mut body = Vec::new();
 
-        let mut list_before = false;
-        let mut list: Vec<Tag> = Vec::new();
+        for line in s.lines() {
+            body.extend(parser.line(line)?);
+        }
 
-        let mut title: Option<String> = None;
+        body.extend(parser.finish()?);
 
-        for line in s.lines() {
-            let mut line = line.trim().to_owned();
-
-            if preformatted && !line.starts_with("```") {
-                preformatted_text.push(line);
-            } else if list_before && !line.starts_with("* ") {
-                page.push(Tag::List {
-                    body: list.clone(),
-                    style: ListStyle::Disc,
-                });
-                list_before = false;
-                list.clear();
-            } else if line.starts_with("=>") {
-                let body = line.split_off(2);
-                let mut body = body.trim().splitn(2, char::is_whitespace);
-
-                let url = body.next().ok_or(Error::InvalidSyntax)?.trim();
-
-                match body.next() {
-                    Some(label) => page.push(Paragraph {
-                        body: vec![NavLink {
-                            body: Some(label.trim().into()),
-                            dref: url.into(),
-                        }]
-                        .into(),
-                    }),
-                    None => page.push(Paragraph {
-                        body: vec![NavLink {
-                            body: None,
-                            dref: url.into(),
-                        }]
-                        .into(),
-                    }),
-                };
-            } else if line.starts_with("# ") {
-                let body = line.split_off(2).trim().to_owned();
+        Ok(Page {
+            title: parser.title,
+            description: None,
+            body,
+            variables: None,
+        })
+    }
 
-                if title == None {
-                    title = Some(body.clone())
-                }
+    fn process_bytes(&self, _: Vec<u8>, _: &Metadata) -> Result<Page, Error> {
+        Err(Error::UnsupportedInput)
+    }
+}
+
+impl LineInput for GemtextInput {
+    fn parser(&self, meta: &Metadata) -> Box<dyn LineParser> {
+        Box::new(GemtextParser::new(meta.url.clone()))
+    }
+}
+
+/// Gemtext parser fed line by line
+#[derive(Default)]
+pub struct GemtextParser {
+    preformatted: Option<Vec<String>>,
+    list: Vec<Tag>,
+    title: Option<String>,
+    base: Option<Url>,
+}
 
-                page.push(Heading {
-                    body,
-                    heading: HeadingLevel::One,
-                });
-            } else if line.starts_with("## ") {
-                let body = line.split_off(3);
-                page.push(Heading {
-                    body: body.trim().into(),
-                    heading: HeadingLevel::Two,
-                });
-            } else if line.starts_with("### ") {
-                let body = line.split_off(4);
-                page.push(Heading {
-                    body: body.trim().into(),
-                    heading: HeadingLevel::Three,
-                });
-            } else if line.starts_with("* ") {
-                let body = line.split_off(2);
-                list.push(Element { body: body.into() });
-                list_before = true;
-            } else if line.starts_with("> ") {
-                let body = line.split_off(2);
-                page.push(BlockQuote { body: body.into() });
-            } else if line.starts_with("```") {
-                if preformatted {
-                    page.push(Code {
-                        body: preformatted_text.join("\n"),
+impl GemtextParser {
+    /// Parser resolving relative links against base url
+    pub fn new(base: Option<Url>) -> Self {
+        Self {
+            base,
+            ..Default::default()
+        }
+    }
+}
+
+impl LineParser for GemtextParser {
+    fn line(&mut self, line: &str) -> Result<Vec<Tag>, Error> {
+        let mut tags = Vec::new();
+
+        if let Some(text) = &mut self.preformatted {
+            match line.starts_with("```") {
+                true => {
+                    tags.push(Code {
+                        body: text.join("\n"),
                         language: None,
                     });
-                    preformatted_text.clear();
+                    self.preformatted = None;
                 }
+                false => text.push(line.to_owned()),
+            }
 
-                preformatted = !preformatted;
-            } else if !line.is_empty() {
-                page.push(Paragraph { body: line.into() });
+            return Ok(tags);
+        }
+
+        // Line type is only recognised at start of line, so text lines can be escaped by space
+        let line = line.trim_end();
+
+        if !self.list.is_empty() && !line.starts_with("* ") {
+            tags.push(List {
+                body: std::mem::take(&mut self.list),
+                style: ListStyle::Disc,
+            });
+        }
+
+        if let Some(body) = line.strip_prefix("=>") {
+            let mut body = body.trim().splitn(2, char::is_whitespace);
+
+            let url = body.next().ok_or(Error::InvalidSyntax)?.trim();
+
+            tags.push(Paragraph {
+                body: vec![NavLink {
+                    body: body.next().map(|label| label.trim().into()),
+                    dref: resolve_link(self.base.as_ref(), url),
+                }]
+                .into(),
+            });
+        } else if let Some(body) = line.strip_prefix("# ") {
+            let body = body.trim().to_owned();
+
+            if self.title.is_none() {
+                self.title = Some(body.clone())
             }
+
+            tags.push(Heading {
+                body,
+                heading: HeadingLevel::One,
+            });
+        } else if let Some(body) = line.strip_prefix("## ") {
+            tags.push(Heading {
+                body: body.trim().into(),
+                heading: HeadingLevel::Two,
+            });
+        } else if let Some(body) = line.strip_prefix("### ") {
+            tags.push(Heading {
+                body: body.trim().into(),
+                heading: HeadingLevel::Three,
+            });
+        } else if let Some(body) = line.strip_prefix("* ") {
+            self.list.push(Element { body: body.into() });
+        } else if let Some(body) = line.strip_prefix("> ") {
+            tags.push(BlockQuote { body: body.into() });
+        } else if line.starts_with("```") {
+            self.preformatted = Some(Vec::new());
+        } else if !line.trim_start().is_empty() {
+            tags.push(Paragraph {
+                body: line.trim_start().into(),
+            });
         }
 
-        if list_before {
-            page.push(List {
-                body: list.clone(),
+        Ok(tags)
+    }
+
+    fn finish(&mut self) -> Result<Vec<Tag>, Error> {
+        let mut tags = Vec::new();
+
+        if !self.list.is_empty() {
+            tags.push(List {
+                body: std::mem::take(&mut self.list),
                 style: ListStyle::Disc,
             });
         }
 
-        if preformatted {
-            page.push(Code {
-                body: preformatted_text.join("\n"),
+        if let Some(text) = self.preformatted.take() {
+            tags.push(Code {
+                body: text.join("\n"),
                 language: None,
             });
         }
 
-        Ok(Page {
-            title,
-            description: None,
-            body: page,
-            variables: None,
-        })
+        Ok(tags)
     }
 
-    fn process_bytes(&self, _: Vec<u8>, _: Option<&Url>) -> Result<Page, Error> {
-        Err(Error::UnsupportedInput)
+    fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+}
+
+pub struct GemtextOutput;
+
+impl OutputHandler for GemtextOutput {
+    fn render(&self, page: &Page) -> Result<Output, Error> {
+        let mut writer = GemtextWriter {
+            lines: Vec::new(),
+            footnotes: Vec::new(),
+            vars: page.variables.as_deref().unwrap_or_default(),
+            last_links: false,
+        };
+
+        for tag in &page.body {
+            writer.block(tag);
+        }
+
+        if !writer.footnotes.is_empty() {
+            let footnotes = std::mem::take(&mut writer.footnotes);
+
+            writer.push_block(vec!["## Footnotes".into()], false);
+            writer.push_block(
+                footnotes
+                    .into_iter()
+                    .map(|(n, body)| format!("[{}] {}", n + 1, body))
+                    .collect(),
+                false,
+            );
+        }
+
+        let mut out = writer.lines.join("\n");
+        out.push('\n');
+
+        Ok(Output::Text(out))
+    }
+}
+
+/// Inline links of block, written as link lines after it
+type Links = Vec<(String, Option<String>)>;
+
+struct GemtextWriter<'a> {
+    lines: Vec<String>,
+    footnotes: Vec<(u64, String)>,
+    vars: &'a [String],
+    last_links: bool,
+}
+
+impl GemtextWriter<'_> {
+    fn block(&mut self, tag: &Tag) {
+        match tag {
+            Element { body } | Paragraph { body } => match body {
+                Body::Tags(tags) if has_block(tags) => {
+                    for tag in tags {
+                        self.block(tag);
+                    }
+                }
+                Body::Tags(tags) if tags.iter().all(|t| is_link(t) || is_blank(t)) => {
+                    let mut links = Links::new();
+
+                    for tag in tags {
+                        self.inline(tag, &mut links);
+                    }
+
+                    self.push_block(link_lines(links), true);
+                }
+                body => {
+                    let mut links = Links::new();
+                    let text = self.inline_body(body, &mut links);
+
+                    let mut lines: Vec<String> = text.lines().map(text_line).collect();
+                    lines.extend(link_lines(links));
+                    self.push_block(lines, false);
+                }
+            },
+
+            Heading { body, heading } => {
+                let prefix = match heading {
+                    HeadingLevel::One => "#",
+                    HeadingLevel::Two => "##",
+                    _ => "###",
+                };
+
+                self.push_block(vec![format!("{prefix} {}", one_line(body))], false);
+            }
+
+            Link { .. } | NavLink { .. } | Button { .. } | NavButton { .. } | Image { .. } => {
+                let mut links = Links::new();
+                self.inline(tag, &mut links);
+                self.push_block(link_lines(links), true);
+            }
+
+            Table { body } if body.is_empty() => {}
+
+            Table { body } => {
+                let mut links = Links::new();
+                let rows = body
+                    .iter()
+                    .map(|row| match row {
+                        TableRows::Primary(cells) => (true, self.cells(cells, &mut links)),
+                        TableRows::Default(cells) => (false, self.cells(cells, &mut links)),
+                    })
+                    .collect();
+
+                let mut lines = vec!["```".to_owned()];
+                lines.extend(ascii_table(rows));
+                lines.push("```".into());
+                lines.extend(link_lines(links));
+
+                self.push_block(lines, false);
+            }
+
+            List { body, style } => {
+                let mut lines = Vec::new();
+                self.list(&mut lines, body, style);
+                self.push_block(lines, false);
+            }
+
+            BlockQuote { body } => {
+                let mut links = Links::new();
+                let text = self.inline_body(body, &mut links);
+
+                let mut lines: Vec<String> = text.lines().map(|l| format!("> {l}")).collect();
+                lines.extend(link_lines(links));
+                self.push_block(lines, false);
+            }
+
+            Code { body, language } => {
+                let mut lines = vec![format!("```{}", language.as_deref().unwrap_or_default())];
+                lines.extend(body.lines().map(String::from));
+                lines.push("```".into());
+
+                self.push_block(lines, false);
+            }
+
+            Mono { body } => {
+                let mut lines = vec!["```".to_owned()];
+                lines.extend(body.lines().map(String::from));
+                lines.push("```".into());
+
+                self.push_block(lines, false);
+            }
+
+            FootNote { body, footnote } => self.footnotes.push((*footnote, one_line(body))),
+
+            Block { body } | Flex { body, .. } | Grid { body, .. } | Carousel { body } => {
+                for tag in body {
+                    self.block(tag);
+                }
+            }
+
+            Disclosure { body, title } => {
+                if let Some(title) = title {
+                    self.push_block(vec![format!("### {}", one_line(title))], false);
+                }
+
+                self.block(&Element { body: body.clone() });
+            }
+
+            HorizontalBreak => self.push_block(vec!["---".into()], false),
+
+            Anchor { .. } => {}
+
+            Bold { .. }
+            | Italic { .. }
+            | Strikethrough { .. }
+            | Superscript { .. }
+            | Subscript { .. }
+            | FootLink { .. }
+            | InlineCode { .. }
+            | Variable { .. } => {
+                let mut links = Links::new();
+                let text = self.inline(tag, &mut links);
+
+                let mut lines = vec![text_line(&text)];
+                lines.extend(link_lines(links));
+                self.push_block(lines, false);
+            }
+        }
+    }
+
+    /// Writes list items as flat `*` lines, nested lists go right after their item
+    fn list(&mut self, lines: &mut Vec<String>, body: &[Tag], style: &ListStyle) {
+        for (i, item) in body.iter().enumerate() {
+            let mut links = Links::new();
+            let mut nested: Vec<(&[Tag], &ListStyle)> = Vec::new();
+
+            let text = match item {
+                Element {
+                    body: Body::Tags(tags),
+                } => {
+                    let mut text = String::new();
+
+                    for tag in tags {
+                        match tag {
+                            List { body, style } => nested.push((body, style)),
+                            tag => {
+                                if !text.is_empty() && is_block(tag) {
+                                    text.push(' ');
+                                }
+                                text.push_str(&self.inline(tag, &mut links));
+                            }
+                        }
+                    }
+
+                    text
+                }
+                List { body, style } => {
+                    nested.push((body, style));
+                    String::new()
+                }
+                item => self.inline(item, &mut links),
+            };
+
+            if !text.trim().is_empty() {
+                match style {
+                    ListStyle::Decimal => lines.push(format!("* {}. {}", i + 1, one_line(&text))),
+                    _ => lines.push(format!("* {}", one_line(&text))),
+                }
+            }
+
+            lines.extend(link_lines(links));
+
+            for (body, style) in nested {
+                self.list(lines, body, style);
+            }
+        }
+    }
+
+    fn cells(&mut self, cells: &[Tag], links: &mut Links) -> Vec<String> {
+        cells
+            .iter()
+            .map(|cell| one_line(&self.inline(cell, links)))
+            .collect()
     }
+
+    fn inline(&mut self, tag: &Tag, links: &mut Links) -> String {
+        match tag {
+            Element { body } | Paragraph { body } | BlockQuote { body } => {
+                self.inline_body(body, links)
+            }
+
+            Link { body, dref }
+            | NavLink { body, dref }
+            | Button { body, dref }
+            | NavButton { body, dref } => {
+                let label = body.as_ref().map(|body| self.inline_body(body, links));
+                links.push((dref.clone(), label.clone()));
+
+                label.unwrap_or_else(|| dref.clone())
+            }
+
+            Image { src, alt } => {
+                links.push((src.clone(), alt.clone()));
+                alt.clone().unwrap_or_default()
+            }
+
+            Heading { body, .. }
+            | Bold { body }
+            | Italic { body }
+            | Strikethrough { body }
+            | Superscript { body }
+            | Subscript { body }
+            | Mono { body }
+            | Code { body, .. }
+            | InlineCode { body, .. } => body.clone(),
+
+            Table { body } => body
+                .iter()
+                .map(|row| match row {
+                    TableRows::Primary(cells) | TableRows::Default(cells) => {
+                        self.cells(cells, links).join(" | ")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+
+            List { body, .. }
+            | Block { body }
+            | Flex { body, .. }
+            | Grid { body, .. }
+            | Carousel { body } => body
+                .iter()
+                .map(|tag| self.inline(tag, links))
+                .collect::<Vec<_>>()
+                .join(" "),
+
+            Disclosure { body, title } => {
+                let body = self.inline_body(body, links);
+
+                match title {
+                    Some(title) => format!("{title} {body}"),
+                    None => body,
+                }
+            }
+
+            FootLink { footnote } => format!("[{}]", footnote + 1),
+
+            FootNote { body, footnote } => {
+                self.footnotes.push((*footnote, one_line(body)));
+                String::new()
+            }
+
+            Variable { idx } => usize::try_from(*idx)
+                .ok()
+                .and_then(|idx| self.vars.get(idx))
+                .cloned()
+                .unwrap_or_default(),
+
+            Anchor { .. } | HorizontalBreak => String::new(),
+        }
+    }
+
+    fn inline_body(&mut self, body: &Body, links: &mut Links) -> String {
+        match body {
+            Body::Text(text) => text.clone(),
+            Body::Tags(tags) => tags.iter().map(|tag| self.inline(tag, links)).collect(),
+        }
+    }
+
+    /// Pushes lines of block, separated by empty line from previous block.
+    /// Consecutive link blocks are kept together
+    fn push_block(&mut self, lines: Vec<String>, links: bool) {
+        if lines.is_empty() {
+            return;
+        }
+
+        let keep_together = links && self.last_links;
+
+        if !self.lines.is_empty() && !keep_together {
+            self.lines.push(String::new());
+        }
+
+        self.lines.extend(lines);
+        self.last_links = links;
+    }
+}
+
+fn is_link(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Link { .. } | NavLink { .. } | Button { .. } | NavButton { .. } | Image { .. }
+    )
+}
+
+fn link_lines(links: Links) -> Vec<String> {
+    links
+        .into_iter()
+        .map(|(dref, label)| match label {
+            Some(label) if !label.trim().is_empty() => format!("=> {dref} {}", one_line(&label)),
+            _ => format!("=> {dref}"),
+        })
+        .collect()
+}
+
+/// Keeps text line from being parsed as gemtext line type
+fn text_line(line: &str) -> String {
+    let line = line.trim();
+
+    match ["=>", "#", "* ", ">", "```"]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+    {
+        true => format!(" {line}"),
+        false => line.to_owned(),
+    }
+}
+
+fn one_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
 }
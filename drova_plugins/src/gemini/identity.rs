@@ -0,0 +1,278 @@
+use std::{collections::HashMap, fmt, fs, io, path::PathBuf, sync::RwLock};
+
+use drova_sdk::requester::Error;
+use rcgen::{CertificateParams, DnType, KeyPair};
+use tokio_rustls::rustls::pki_types::{pem::PemObject, CertificateDer, PrivatePkcs8KeyDer};
+use url::Url;
+
+/// Client certificate presented to capsules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    /// Name of identity, also used as certificate common name
+    pub name: String,
+    /// DER encoded certificate
+    pub cert: Vec<u8>,
+    /// DER encoded PKCS#8 private key
+    pub key: Vec<u8>,
+    /// Parts of capsules identity is used for
+    pub scopes: Vec<Scope>,
+}
+
+impl Identity {
+    /// Generate identity with new self-signed certificate
+    pub fn generate(name: &str) -> Result<Self, Error> {
+        let key = KeyPair::generate().map_err(|_| Error::InvalidCert)?;
+
+        let mut params = CertificateParams::default();
+        params.distinguished_name.push(DnType::CommonName, name);
+
+        let cert = params.self_signed(&key).map_err(|_| Error::InvalidCert)?;
+
+        Ok(Self {
+            name: name.to_owned(),
+            cert: cert.der().to_vec(),
+            key: key.serialize_der(),
+            scopes: Vec::new(),
+        })
+    }
+
+    /// Use identity for url and everything under it
+    pub fn add_scope(&mut self, url: &Url) -> Result<(), Error> {
+        let scope = Scope::from_url(url).ok_or(Error::InvalidUrl)?;
+
+        if !self.scopes.contains(&scope) {
+            self.scopes.push(scope);
+        }
+
+        Ok(())
+    }
+
+    /// Path length of most specific scope matching url, if any
+    fn matching_scope(&self, url: &Url) -> Option<usize> {
+        self.scopes
+            .iter()
+            .filter(|scope| scope.matches(url))
+            .map(|scope| scope.path.len())
+            .max()
+    }
+}
+
+/// Host, port and path under which identity is used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scope {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Scope {
+    pub fn from_url(url: &Url) -> Option<Self> {
+        Some(Self {
+            host: url.host_str()?.to_ascii_lowercase(),
+            port: url.port().unwrap_or(1965),
+            path: url.path().trim_end_matches('/').to_owned(),
+        })
+    }
+
+    /// Host and port must be same, path must be same or start with scope path
+    /// followed by `/`, so `/app` matches `/app/x` but not `/apple`
+    pub fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+
+        if !host.eq_ignore_ascii_case(&self.host) || url.port().unwrap_or(1965) != self.port {
+            return false;
+        }
+
+        match url.path().strip_prefix(self.path.as_str()) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "gemini://{}:{}{}", self.host, self.port, self.path)
+    }
+}
+
+/// Storage of client certificates
+pub trait IdentityStore: Send + Sync {
+    fn identities(&self) -> Vec<Identity>;
+    /// Add identity or replace one with the same name
+    fn set(&self, identity: Identity) -> Result<(), Error>;
+    fn remove(&self, name: &str) -> Result<(), Error>;
+
+    /// Identity with most specific scope matching url
+    fn find(&self, url: &Url) -> Option<Identity> {
+        self.identities()
+            .into_iter()
+            .filter_map(|identity| Some((identity.matching_scope(url)?, identity)))
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, identity)| identity)
+    }
+}
+
+/// Identities kept only in memory
+#[derive(Default)]
+pub struct MemoryIdentities {
+    identities: RwLock<HashMap<String, Identity>>,
+}
+
+impl IdentityStore for MemoryIdentities {
+    fn identities(&self) -> Vec<Identity> {
+        self.identities.read().unwrap().values().cloned().collect()
+    }
+
+    fn set(&self, identity: Identity) -> Result<(), Error> {
+        let mut identities = self.identities.write().unwrap();
+        identities.insert(identity.name.clone(), identity);
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<(), Error> {
+        self.identities.write().unwrap().remove(name);
+        Ok(())
+    }
+}
+
+/// Identities kept in directory, each as `name.crt` and `name.key` PEM files
+/// with scopes listed line by line in `name.scopes`
+pub struct FileIdentities {
+    dir: PathBuf,
+    identities: RwLock<HashMap<String, Identity>>,
+}
+
+impl FileIdentities {
+    /// Read identities from directory, it is created if missing
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(io_error)?;
+
+        let mut identities = HashMap::new();
+
+        for entry in fs::read_dir(&dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+
+            if path.extension().is_none_or(|ext| ext != "crt") {
+                continue;
+            }
+
+            let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+                continue;
+            };
+
+            let cert = CertificateDer::from_pem_file(&path).map_err(|_| Error::InvalidCert)?;
+            let key = PrivatePkcs8KeyDer::from_pem_file(path.with_extension("key"))
+                .map_err(|_| Error::InvalidCert)?;
+
+            let scopes = match fs::read_to_string(path.with_extension("scopes")) {
+                Ok(scopes) => scopes
+                    .lines()
+                    .filter_map(|scope| Scope::from_url(&Url::parse(scope).ok()?))
+                    .collect(),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => Err(io_error(e))?,
+            };
+
+            identities.insert(
+                name.to_owned(),
+                Identity {
+                    name: name.to_owned(),
+                    cert: cert.to_vec(),
+                    key: key.secret_pkcs8_der().to_vec(),
+                    scopes,
+                },
+            );
+        }
+
+        Ok(Self {
+            dir,
+            identities: RwLock::new(identities),
+        })
+    }
+
+    fn path(&self, name: &str, ext: &str) -> Result<PathBuf, Error> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(Error::IoError(format!("invalid identity name {name:?}")));
+        }
+
+        Ok(self.dir.join(format!("{name}.{ext}")))
+    }
+}
+
+impl IdentityStore for FileIdentities {
+    fn identities(&self) -> Vec<Identity> {
+        self.identities.read().unwrap().values().cloned().collect()
+    }
+
+    fn set(&self, identity: Identity) -> Result<(), Error> {
+        let mut identities = self.identities.write().unwrap();
+
+        fs::write(
+            self.path(&identity.name, "crt")?,
+            pem("CERTIFICATE", &identity.cert),
+        )
+        .map_err(io_error)?;
+        write_private(
+            self.path(&identity.name, "key")?,
+            pem("PRIVATE KEY", &identity.key),
+        )?;
+        let scopes: Vec<String> = identity.scopes.iter().map(Scope::to_string).collect();
+        fs::write(self.path(&identity.name, "scopes")?, scopes.join("\n")).map_err(io_error)?;
+
+        identities.insert(identity.name.clone(), identity);
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<(), Error> {
+        let mut identities = self.identities.write().unwrap();
+
+        for ext in ["crt", "key", "scopes"] {
+            match fs::remove_file(self.path(name, ext)?) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(io_error(e))?,
+                _ => {}
+            }
+        }
+
+        identities.remove(name);
+        Ok(())
+    }
+}
+
+/// Writes file readable only by owner
+fn write_private(path: PathBuf, data: String) -> Result<(), Error> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    io::Write::write_all(&mut options.open(path).map_err(io_error)?, data.as_bytes())
+        .map_err(io_error)
+}
+
+fn pem(label: &str, der: &[u8]) -> String {
+    use base64ct::{Base64, Encoding};
+
+    let encoded = Base64::encode_string(der);
+    let mut out = format!("-----BEGIN {label}-----\n");
+
+    for line in encoded.as_bytes().chunks(64) {
+        out.push_str(&String::from_utf8_lossy(line));
+        out.push('\n');
+    }
+
+    out.push_str(&format!("-----END {label}-----\n"));
+    out
+}
+
+fn io_error(e: io::Error) -> Error {
+    Error::IoError(e.to_string())
+}
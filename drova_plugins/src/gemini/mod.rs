@@ -1,2 +1,4 @@
 pub mod gemtext;
+pub mod identity;
 pub mod protocol;
+pub mod tofu;
@@ -1,52 +1,206 @@
-use std::str;
+use std::sync::Arc;
 
 use async_trait::async_trait;
-use drova_sdk::requester::{Error, ProtocolHandler, Response, ResponseData};
-use tokio_gemini::{certs::SelfsignedCertVerifier, LibError, StatusCode};
+use drova_sdk::requester::{
+    BodyStream, Error, FetchOptions, Metadata, ProtocolHandler, Response, ResponseData,
+};
+use tokio::{
+    io::{self, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use tokio_gemini::{
+    certs::ServerName, client::Response as GeminiResponse, InvalidUrl, LibError, Status, StatusCode,
+};
+use tokio_rustls::{
+    rustls::{
+        self,
+        crypto::{ring::default_provider, CryptoProvider},
+        pki_types::{CertificateDer, PrivatePkcs8KeyDer},
+        ClientConfig, OtherError,
+    },
+    TlsConnector,
+};
+use url::Url;
 
-use crate::utils::mime_to_str;
+use super::{
+    identity::{Identity, IdentityStore, MemoryIdentities},
+    tofu::{CertChanged, KnownHostsStore, MemoryKnownHosts, TofuVerifier},
+};
+use crate::utils::{decode_stream, decode_text, is_text, mime_params, mime_to_str};
 
-pub struct GeminiProtocol;
+pub struct GeminiProtocol {
+    provider: Arc<CryptoProvider>,
+    known_hosts: Arc<dyn KnownHostsStore>,
+    identities: Arc<dyn IdentityStore>,
+}
 
-#[async_trait]
-impl ProtocolHandler for GeminiProtocol {
-    async fn fetch(&self, url: &url::Url) -> Result<Response, Error> {
-        let client = tokio_gemini::Client::builder()
-            .with_selfsigned_cert_verifier(CertVerifier)
-            .build();
+impl GeminiProtocol {
+    /// Gemini protocol trusting server certificates on first use,
+    /// pinned certificates are kept in `known_hosts`.
+    /// Client certificates from `identities` are presented to urls in their scopes
+    pub fn new(known_hosts: Arc<dyn KnownHostsStore>, identities: Arc<dyn IdentityStore>) -> Self {
+        Self {
+            provider: Arc::new(default_provider()),
+            known_hosts,
+            identities,
+        }
+    }
+
+    /// Pinned server certificates, e.g. for inspecting or forgetting hosts
+    pub fn known_hosts(&self) -> &Arc<dyn KnownHostsStore> {
+        &self.known_hosts
+    }
+
+    /// Client certificates, e.g. for creating identity when capsule asks for it
+    pub fn identities(&self) -> &Arc<dyn IdentityStore> {
+        &self.identities
+    }
+
+    async fn request(
+        &self,
+        url: &Url,
+        opts: &FetchOptions,
+    ) -> Result<GeminiResponse<BodyStream>, LibError> {
+        let host = url.host_str().ok_or(InvalidUrl::ConvertError)?;
+        let port = url.port().unwrap_or(1965);
+
+        let domain = ServerName::try_from(host)
+            .map_err(|_| InvalidUrl::ConvertError)?
+            .to_owned();
+
+        let verifier =
+            TofuVerifier::new(self.provider.clone(), self.known_hosts.clone(), host, port);
+        let connector = connector(
+            self.provider.clone(),
+            verifier,
+            self.identities.find(url).as_ref(),
+        )?;
 
-        let mut resp = client
-            .request(url.to_string())
+        let mut stream = opts
+            .connect(async {
+                let stream = connect(host, port).await?;
+                Ok::<_, LibError>(connector.connect(domain, stream).await?)
+            })
             .await
-            .map_err(match_lib_err)?;
+            .map_err(io::Error::other)??;
+
+        stream.write_all(url.as_str().as_bytes()).await?;
+        stream.write_all(b"\r\n").await?;
+        stream.flush().await?;
+
+        let mut status = [0u8; 3];
+        opts.first_byte(stream.read_exact(&mut status))
+            .await
+            .map_err(io::Error::other)??;
+        let status = Status::parse_status(&status)?;
+
+        let mut stream = BufReader::new(stream);
+
+        // Meta is at most 1024 bytes (gemini spec), followed by CRLF
+        let max_meta = opts.max_header_size.unwrap_or(1024) + 2;
+
+        let mut message = Vec::new();
+        (&mut stream)
+            .take(max_meta as u64)
+            .read_until(b'\n', &mut message)
+            .await?;
+
+        if message.len() == max_meta && message.last() != Some(&b'\n') {
+            return Err(io::Error::other(Error::ExceededHeaderSize).into());
+        }
+
+        while message.last().is_some_and(|c| *c == b'\n' || *c == b'\r') {
+            message.pop();
+        }
+
+        Ok(GeminiResponse::new(
+            status,
+            String::from_utf8(message)?,
+            BodyStream::new(stream),
+        ))
+    }
+
+    async fn response(
+        &self,
+        url: &Url,
+        opts: &FetchOptions,
+        stream: bool,
+    ) -> Result<Response, Error> {
+        let mut resp = self.request(url, opts).await.map_err(match_lib_err)?;
 
         match resp.status().status_code() {
             StatusCode::Success => {
-                let ty = mime_to_str(resp.mime().map_err(match_lib_err)?);
+                let mime = resp.mime().map_err(match_lib_err)?;
+                let ty = mime_to_str(&mime);
+                let meta = Metadata {
+                    url: Some(url.clone()),
+                    params: mime_params(&mime),
+                    ..Default::default()
+                };
+
+                let body = std::mem::replace(resp.stream(), BodyStream::new(io::empty()));
+
+                if stream {
+                    // Text is decoded to UTF-8, which requester expects
+                    let text = is_text(&mime);
+                    let body = match text {
+                        true => decode_stream(body, &mime),
+                        false => body,
+                    };
+
+                    return Ok(Response {
+                        data: ResponseData::Stream { body, text },
+                        ty,
+                        meta,
+                    });
+                }
+
+                let mut bytes = Vec::new();
+                body.max_size(opts.max_body_size)
+                    .read_to_end(&mut bytes)
+                    .await?;
 
-                match ty.starts_with("text") {
+                match is_text(&mime) {
                     true => Ok(Response {
-                        data: ResponseData::TextOutput(resp.text().await.map_err(match_lib_err)?),
+                        data: ResponseData::TextOutput(decode_text(&bytes, &mime)?),
                         ty,
+                        meta,
                     }),
                     false => Ok(Response {
-                        data: ResponseData::BitsOutput(
-                            resp.bytes().await.map_err(match_lib_err)?.to_vec(),
-                        ),
+                        data: ResponseData::BitsOutput(bytes),
                         ty,
+                        meta,
                     }),
                 }
             }
-            StatusCode::Input => Err(Error::GetInput),
-            StatusCode::InputSensitive => Err(Error::GetSecureInput),
+            StatusCode::Input => Err(Error::GetInput(resp.message().into())),
+            StatusCode::InputSensitive => Err(Error::GetSecureInput(resp.message().into())),
             StatusCode::NotFound => Err(Error::NotFound),
-            StatusCode::TempRedirect => Err(Error::ExceededMaxRedirects),
-            StatusCode::PermRedirect => Err(Error::ExceededMaxRedirects),
+            StatusCode::TempRedirect => Ok(Response {
+                data: ResponseData::Redirect {
+                    url: url.join(resp.message().trim())?,
+                    permanent: false,
+                },
+                ty: String::new(),
+                meta: Metadata::default(),
+            }),
+            StatusCode::PermRedirect => Ok(Response {
+                data: ResponseData::Redirect {
+                    url: url.join(resp.message().trim())?,
+                    permanent: true,
+                },
+                ty: String::new(),
+                meta: Metadata::default(),
+            }),
             StatusCode::TempFail => Err(Error::TemporalFailure),
-            StatusCode::ServerUnavailable => Err(Error::ServerUnavailable),
+            StatusCode::ServerUnavailable => Err(Error::ServerUnavailable(None)),
             StatusCode::CgiError => Err(Error::Failure),
             StatusCode::ProxyError => Err(Error::UnsupportedProtocol),
-            StatusCode::SlowDown => Err(Error::TooManyRequests),
+            // Meta of slow down is number of seconds to wait
+            StatusCode::SlowDown => {
+                let wait = resp.message().trim().parse().ok();
+                Err(Error::TooManyRequests(wait))
+            }
             StatusCode::PermFail => Err(Error::Failure),
             StatusCode::Gone => Err(Error::Gone),
             StatusCode::ProxyRequestRefused => Err(Error::BadRequest),
@@ -59,23 +213,75 @@ impl ProtocolHandler for GeminiProtocol {
     }
 }
 
-// TODO: remove when there will be file cert verifier in tokio_gemini
-struct CertVerifier;
+impl Default for GeminiProtocol {
+    fn default() -> Self {
+        Self::new(
+            Arc::new(MemoryKnownHosts::default()),
+            Arc::new(MemoryIdentities::default()),
+        )
+    }
+}
+
 #[async_trait]
-impl SelfsignedCertVerifier for CertVerifier {
-    async fn verify(
-        &self,
-        _: &tokio_gemini::certs::CertificateDer<'_>,
-        _: &str,
-        _: u16,
-    ) -> Result<bool, tokio_gemini::LibError> {
-        Ok(true)
+impl ProtocolHandler for GeminiProtocol {
+    async fn fetch(&self, url: &Url, opts: &FetchOptions) -> Result<Response, Error> {
+        self.response(url, opts, false).await
+    }
+
+    async fn fetch_stream(&self, url: &Url, opts: &FetchOptions) -> Result<Response, Error> {
+        self.response(url, opts, true).await
+    }
+}
+
+/// Self-signed certificates are accepted, if they match ones pinned by [`TofuVerifier`]
+fn connector(
+    provider: Arc<CryptoProvider>,
+    verifier: TofuVerifier,
+    identity: Option<&Identity>,
+) -> Result<TlsConnector, LibError> {
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+
+    let config = match identity {
+        Some(identity) => config.with_client_auth_cert(
+            vec![CertificateDer::from(identity.cert.clone())],
+            PrivatePkcs8KeyDer::from(identity.key.clone()).into(),
+        )?,
+        None => config.with_no_client_auth(),
+    };
+
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+async fn connect(host: &str, port: u16) -> Result<TcpStream, LibError> {
+    let mut last_err = None;
+
+    for addr in tokio::net::lookup_host((host, port)).await? {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = Some(e),
+        }
+    }
+
+    match last_err {
+        Some(e) => Err(e.into()),
+        None => Err(LibError::HostLookupError),
     }
 }
 
 fn match_lib_err(e: LibError) -> Error {
     match e {
-        LibError::IoError(e) => Error::IoError(e.to_string()),
+        // Failed handshake is reported by tokio-rustls as io error
+        LibError::IoError(e) => match e.get_ref().and_then(|e| e.downcast_ref::<rustls::Error>()) {
+            Some(rustls::Error::Other(OtherError(e))) => match e.downcast_ref::<CertChanged>() {
+                Some(CertChanged(host)) => Error::CertChanged(host.clone()),
+                None => Error::TlsFailed,
+            },
+            Some(_) => Error::TlsFailed,
+            None => e.into(),
+        },
         LibError::InvalidUrlError(_) => Error::InvalidUrl,
         LibError::HostLookupError => Error::DnsFailed,
         LibError::RustlsError(_) => Error::TlsFailed,
@@ -0,0 +1,444 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use drova_sdk::requester::Error;
+use tokio_gemini::certs::{fingerprint::CertFingerprint, verifier::InternalCertVerifier};
+use tokio_rustls::rustls::{
+    self,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    DigitallySignedStruct, OtherError, SignatureScheme,
+};
+use x509_parser::parse_x509_certificate;
+
+/// Certificate pinned for host on first use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownHost {
+    /// Base64 sha256 fingerprint of certificate
+    pub fingerprint: String,
+    /// Unix time when certificate expires
+    pub expires: u64,
+}
+
+/// Storage of pinned certificates, keyed by `host` or `host:port` for non-default ports
+pub trait KnownHostsStore: Send + Sync {
+    fn get(&self, host: &str) -> Option<KnownHost>;
+    fn set(&self, host: &str, known: KnownHost) -> Result<(), Error>;
+    /// Forget pinned certificate, so next one will be trusted on first use again
+    fn remove(&self, host: &str) -> Result<(), Error>;
+    fn hosts(&self) -> Vec<(String, KnownHost)>;
+}
+
+/// Known hosts kept only in memory
+#[derive(Default)]
+pub struct MemoryKnownHosts {
+    hosts: RwLock<HashMap<String, KnownHost>>,
+}
+
+impl KnownHostsStore for MemoryKnownHosts {
+    fn get(&self, host: &str) -> Option<KnownHost> {
+        self.hosts.read().unwrap().get(host).cloned()
+    }
+
+    fn set(&self, host: &str, known: KnownHost) -> Result<(), Error> {
+        self.hosts.write().unwrap().insert(host.into(), known);
+        Ok(())
+    }
+
+    fn remove(&self, host: &str) -> Result<(), Error> {
+        self.hosts.write().unwrap().remove(host);
+        Ok(())
+    }
+
+    fn hosts(&self) -> Vec<(String, KnownHost)> {
+        let hosts = self.hosts.read().unwrap();
+        hosts.iter().map(|(h, k)| (h.clone(), k.clone())).collect()
+    }
+}
+
+/// Known hosts file, one host per line:
+/// ```text
+/// #host   expires    algo   base64 fingerprint
+/// dc09.ru 1722930541 sha256 dGVzdHRlc3R0ZXN0Cg
+/// ```
+pub struct FileKnownHosts {
+    path: PathBuf,
+    hosts: RwLock<HashMap<String, KnownHost>>,
+}
+
+impl FileKnownHosts {
+    /// Read known hosts file, it is created on first write if missing
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => Err(Error::IoError(e.to_string()))?,
+        };
+
+        let mut hosts = HashMap::new();
+
+        for line in data.lines() {
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            if let [host, expires, "sha256", fingerprint] =
+                line.split_whitespace().collect::<Vec<_>>()[..]
+            {
+                let Ok(expires) = expires.parse() else {
+                    continue;
+                };
+
+                hosts.insert(
+                    host.to_owned(),
+                    KnownHost {
+                        fingerprint: fingerprint.to_owned(),
+                        expires,
+                    },
+                );
+            }
+        }
+
+        Ok(Self {
+            path,
+            hosts: RwLock::new(hosts),
+        })
+    }
+
+    fn save(&self, hosts: &HashMap<String, KnownHost>) -> Result<(), Error> {
+        let mut data = String::new();
+
+        for (host, known) in hosts {
+            data.push_str(&format!(
+                "{host} {} sha256 {}\n",
+                known.expires, known.fingerprint
+            ));
+        }
+
+        let tmp = self.path.with_extension("tmp");
+
+        fs::write(&tmp, data)
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|e| Error::IoError(e.to_string()))
+    }
+}
+
+impl KnownHostsStore for FileKnownHosts {
+    fn get(&self, host: &str) -> Option<KnownHost> {
+        self.hosts.read().unwrap().get(host).cloned()
+    }
+
+    fn set(&self, host: &str, known: KnownHost) -> Result<(), Error> {
+        let mut hosts = self.hosts.write().unwrap();
+        hosts.insert(host.into(), known);
+        self.save(&hosts)
+    }
+
+    fn remove(&self, host: &str) -> Result<(), Error> {
+        let mut hosts = self.hosts.write().unwrap();
+        hosts.remove(host);
+        self.save(&hosts)
+    }
+
+    fn hosts(&self) -> Vec<(String, KnownHost)> {
+        let hosts = self.hosts.read().unwrap();
+        hosts.iter().map(|(h, k)| (h.clone(), k.clone())).collect()
+    }
+}
+
+/// Trust on first use verifier of one host. Certificate of host may change
+/// only after pinned one expires. It runs inside TLS handshake,
+/// so client certificate is never sent to server with changed certificate
+pub(super) struct TofuVerifier {
+    inner: InternalCertVerifier,
+    hosts: Arc<dyn KnownHostsStore>,
+    key: String,
+}
+
+impl TofuVerifier {
+    pub(super) fn new(
+        provider: Arc<CryptoProvider>,
+        hosts: Arc<dyn KnownHostsStore>,
+        host: &str,
+        port: u16,
+    ) -> Self {
+        // Host of non-special schemes keeps its case in url
+        let host = host.to_ascii_lowercase();
+
+        let key = match port {
+            1965 => host,
+            port => format!("{host}:{port}"),
+        };
+
+        Self {
+            inner: InternalCertVerifier::from(provider),
+            hosts,
+            key,
+        }
+    }
+
+    fn check(&self, cert: &CertificateDer<'_>) -> Result<(), rustls::Error> {
+        let fingerprint = CertFingerprint::new_sha256(cert).base64();
+
+        match self.hosts.get(&self.key) {
+            Some(known) if known.fingerprint == fingerprint => return Ok(()),
+            Some(known) if known.expires > now() => {
+                return Err(other(CertChanged(self.key.clone())));
+            }
+            _ => {}
+        }
+
+        // Certificates without readable expiry are pinned until forgotten
+        let expires = parse_x509_certificate(cert)
+            .ok()
+            .and_then(|(_, cert)| u64::try_from(cert.validity().not_after.timestamp()).ok())
+            .unwrap_or(u64::MAX);
+
+        self.hosts
+            .set(
+                &self.key,
+                KnownHost {
+                    fingerprint,
+                    expires,
+                },
+            )
+            .map_err(other)
+    }
+}
+
+impl fmt::Debug for TofuVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TofuVerifier")
+            .field("key", &self.key)
+            .finish()
+    }
+}
+
+impl ServerCertVerifier for TofuVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        self.check(end_entity)?;
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+fn other(e: impl std::error::Error + Send + Sync + 'static) -> rustls::Error {
+    rustls::Error::Other(OtherError(Arc::new(e)))
+}
+
+/// Pinned certificate of host does not match presented one
+#[derive(Debug)]
+pub(super) struct CertChanged(pub(super) String);
+
+impl fmt::Display for CertChanged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "certificate of {} changed", self.0)
+    }
+}
+
+impl std::error::Error for CertChanged {}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cert() -> CertificateDer<'static> {
+        rcgen::generate_simple_self_signed(vec!["example.org".into()])
+            .unwrap()
+            .cert
+            .der()
+            .clone()
+    }
+
+    fn verifier(hosts: &Arc<MemoryKnownHosts>, host: &str, port: u16) -> TofuVerifier {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        TofuVerifier::new(provider, hosts.clone(), host, port)
+    }
+
+    fn is_changed(result: Result<(), rustls::Error>) -> bool {
+        match result {
+            Err(rustls::Error::Other(OtherError(e))) => e.downcast_ref::<CertChanged>().is_some(),
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn pins_on_first_use() {
+        let hosts = Arc::new(MemoryKnownHosts::default());
+        let cert = cert();
+
+        verifier(&hosts, "example.org", 1965).check(&cert).unwrap();
+
+        let known = hosts.get("example.org").unwrap();
+        assert_eq!(
+            known.fingerprint,
+            CertFingerprint::new_sha256(&cert).base64()
+        );
+        assert!(known.expires > now());
+    }
+
+    #[test]
+    fn accepts_same_certificate() {
+        let hosts = Arc::new(MemoryKnownHosts::default());
+        let cert = cert();
+
+        verifier(&hosts, "example.org", 1965).check(&cert).unwrap();
+        verifier(&hosts, "example.org", 1965).check(&cert).unwrap();
+    }
+
+    #[test]
+    fn rejects_changed_certificate_before_expiry() {
+        let hosts = Arc::new(MemoryKnownHosts::default());
+        let pinned = cert();
+
+        verifier(&hosts, "example.org", 1965)
+            .check(&pinned)
+            .unwrap();
+
+        assert!(is_changed(
+            verifier(&hosts, "example.org", 1965).check(&cert())
+        ));
+        assert_eq!(
+            hosts.get("example.org").unwrap().fingerprint,
+            CertFingerprint::new_sha256(&pinned).base64()
+        );
+    }
+
+    #[test]
+    fn repins_changed_certificate_after_expiry() {
+        let hosts = Arc::new(MemoryKnownHosts::default());
+        let cert = cert();
+
+        hosts
+            .set(
+                "example.org",
+                KnownHost {
+                    fingerprint: "expired".into(),
+                    expires: now() - 1,
+                },
+            )
+            .unwrap();
+
+        verifier(&hosts, "example.org", 1965).check(&cert).unwrap();
+
+        assert_eq!(
+            hosts.get("example.org").unwrap().fingerprint,
+            CertFingerprint::new_sha256(&cert).base64()
+        );
+    }
+
+    #[test]
+    fn host_case_does_not_bypass_pin() {
+        let hosts = Arc::new(MemoryKnownHosts::default());
+
+        verifier(&hosts, "example.org", 1965)
+            .check(&cert())
+            .unwrap();
+
+        assert!(is_changed(
+            verifier(&hosts, "EXAMPLE.org", 1965).check(&cert())
+        ));
+        assert_eq!(hosts.hosts().len(), 1);
+    }
+
+    #[test]
+    fn non_default_port_is_pinned_separately() {
+        let hosts = Arc::new(MemoryKnownHosts::default());
+
+        verifier(&hosts, "example.org", 1965)
+            .check(&cert())
+            .unwrap();
+        verifier(&hosts, "Example.org", 1966)
+            .check(&cert())
+            .unwrap();
+
+        assert!(hosts.get("example.org:1966").is_some());
+    }
+
+    #[test]
+    fn file_known_hosts_round_trip() {
+        let dir = std::env::temp_dir().join(format!("drova-known-hosts-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("known_hosts");
+
+        let first = KnownHost {
+            fingerprint: "Zmlyc3Q".into(),
+            expires: 1722930541,
+        };
+        let second = KnownHost {
+            fingerprint: "c2Vjb25k".into(),
+            expires: u64::MAX,
+        };
+
+        let hosts = FileKnownHosts::open(&path).unwrap();
+        hosts.set("example.org", first.clone()).unwrap();
+        hosts.set("example.org:1966", second.clone()).unwrap();
+        hosts.set("removed.org", first.clone()).unwrap();
+        hosts.remove("removed.org").unwrap();
+
+        let hosts = FileKnownHosts::open(&path).unwrap();
+        let mut saved = hosts.hosts();
+        saved.sort_by(|a, b| a.0.cmp(&b.0));
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            saved,
+            vec![
+                ("example.org".into(), first),
+                ("example.org:1966".into(), second),
+            ]
+        );
+    }
+}
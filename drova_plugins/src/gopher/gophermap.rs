@@ -0,0 +1,103 @@
+use dalet::types::{Page, Tag};
+use drova_sdk::requester::{Error, InputHandler, Metadata};
+use percent_encoding::utf8_percent_encode;
+use url::Url;
+
+use super::SELECTOR_ENCODE_SET;
+
+pub struct GophermapInput;
+
+impl InputHandler for GophermapInput {
+    fn process_text(&self, s: String, meta: &Metadata) -> Result<Page, Error> {
+        let mut page: Vec<Tag> = Vec::new();
+        let mut info: Vec<&str> = Vec::new();
+
+        for line in s.lines() {
+            let line = line.trim_end_matches('\r');
+
+            if line == "." {
+                break;
+            }
+
+            let mut chars = line.chars();
+            let Some(item) = chars.next() else {
+                continue;
+            };
+
+            let mut fields = chars.as_str().split('\t');
+            let display = fields.next().unwrap_or_default();
+
+            if item == 'i' {
+                info.push(display);
+                continue;
+            }
+
+            if !info.is_empty() {
+                page.push(Tag::Mono {
+                    body: info.join("\n"),
+                });
+                info.clear();
+            }
+
+            let selector = fields.next().unwrap_or_default();
+            let host = fields
+                .next()
+                .filter(|host| !host.is_empty())
+                .or_else(|| meta.url.as_ref().and_then(Url::host_str))
+                .unwrap_or_default();
+            let port = fields
+                .next()
+                .and_then(|port| port.trim().parse::<u16>().ok())
+                .unwrap_or(70);
+
+            match item {
+                '3' => page.push(Tag::Paragraph {
+                    body: display.to_owned().into(),
+                }),
+                item => page.push(Tag::Paragraph {
+                    body: vec![Tag::NavLink {
+                        body: Some(display.to_owned().into()),
+                        dref: item_url(item, selector, host, port),
+                    }]
+                    .into(),
+                }),
+            }
+        }
+
+        if !info.is_empty() {
+            page.push(Tag::Mono {
+                body: info.join("\n"),
+            });
+        }
+
+        Ok(Page {
+            title: None,
+            description: None,
+            body: page,
+            variables: None,
+        })
+    }
+
+    fn process_bytes(&self, _: Vec<u8>, _: &Metadata) -> Result<Page, Error> {
+        Err(Error::UnsupportedInput)
+    }
+}
+
+fn item_url(item: char, selector: &str, host: &str, port: u16) -> String {
+    if let Some(url) = selector.strip_prefix("URL:") {
+        return url.to_owned();
+    }
+
+    let port = match port {
+        70 => String::new(),
+        port => format!(":{port}"),
+    };
+
+    match item {
+        '8' | 'T' => format!("telnet://{host}{port}"),
+        item => format!(
+            "gopher://{host}{port}/{item}{}",
+            utf8_percent_encode(selector, SELECTOR_ENCODE_SET)
+        ),
+    }
+}
@@ -0,0 +1,17 @@
+pub mod gophermap;
+pub mod protocol;
+
+use percent_encoding::{AsciiSet, CONTROLS};
+
+/// Characters escaped in selectors of gopher urls
+const SELECTOR_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
@@ -0,0 +1,126 @@
+use async_trait::async_trait;
+use drova_sdk::requester::{
+    BodyStream, Error, FetchOptions, Metadata, ProtocolHandler, Response, ResponseData,
+};
+use percent_encoding::percent_decode_str;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use url::Url;
+
+pub struct GopherProtocol;
+
+#[async_trait]
+impl ProtocolHandler for GopherProtocol {
+    async fn fetch(&self, url: &Url, opts: &FetchOptions) -> Result<Response, Error> {
+        let host = url.host_str().ok_or(Error::InvalidUrl)?;
+        let port = url.port().unwrap_or(70);
+
+        let (item, selector, search) = parse_url(url)?;
+
+        let request = match (item, search) {
+            ('7', Some(search)) => format!("{selector}\t{search}\r\n"),
+            ('7', None) => return Err(Error::GetInput("Search".into())),
+            (_, _) => format!("{selector}\r\n"),
+        };
+
+        let mut stream = opts.connect(TcpStream::connect((host, port))).await??;
+        stream.write_all(request.as_bytes()).await?;
+
+        opts.first_byte(stream.readable()).await??;
+
+        let mut body = Vec::new();
+        BodyStream::new(stream)
+            .max_size(opts.max_body_size)
+            .read_to_end(&mut body)
+            .await?;
+
+        let ty = item_mime(item, &selector);
+        let meta = Metadata {
+            url: Some(url.clone()),
+            ..Default::default()
+        };
+
+        match ty.starts_with("text") {
+            true => Ok(Response {
+                data: ResponseData::TextOutput(strip_last_line(
+                    String::from_utf8_lossy(&body).into_owned(),
+                )),
+                ty: ty.into(),
+                meta,
+            }),
+            false => Ok(Response {
+                data: ResponseData::BitsOutput(body),
+                ty: ty.into(),
+                meta,
+            }),
+        }
+    }
+}
+
+/// Splits gopher url to item type, selector and search (RFC 4266)
+fn parse_url(url: &Url) -> Result<(char, String, Option<String>), Error> {
+    let path = percent_decode_str(url.path())
+        .decode_utf8()
+        .map_err(|_| Error::InvalidUrl)?;
+
+    let mut path = path.strip_prefix('/').unwrap_or(&path).chars();
+
+    let item = path.next().unwrap_or('1');
+    let path: String = path.collect();
+
+    let (selector, search) = match path.split_once('\t') {
+        Some((selector, search)) => (selector.to_owned(), Some(search.to_owned())),
+        None => (path, None),
+    };
+
+    let search = match (search, url.query()) {
+        (Some(search), _) => Some(search),
+        (None, Some(query)) => Some(
+            percent_decode_str(query)
+                .decode_utf8()
+                .map_err(|_| Error::InvalidUrl)?
+                .into_owned(),
+        ),
+        (None, None) => None,
+    };
+
+    Ok((item, selector, search))
+}
+
+fn item_mime(item: char, selector: &str) -> &'static str {
+    match item {
+        '0' => "text/plain",
+        '1' | '7' => "text/gophermap",
+        'h' => "text/html",
+        '6' => "text/x-uuencode",
+        '4' => "application/mac-binhex40",
+        'd' => "application/pdf",
+        'g' => "image/gif",
+        'I' | 'p' => match selector
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase())
+        {
+            Some(ext) if ext == "png" => "image/png",
+            Some(ext) if ext == "jpg" || ext == "jpeg" => "image/jpeg",
+            Some(ext) if ext == "webp" => "image/webp",
+            Some(ext) if ext == "gif" => "image/gif",
+            _ => "application/octet-stream",
+        },
+        's' => "audio/*",
+        ';' => "video/*",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Removes terminating `.` line of text responses
+fn strip_last_line(mut text: String) -> String {
+    let trimmed = text.trim_end_matches(['\r', '\n']);
+
+    if trimmed == "." || trimmed.ends_with("\n.") {
+        text.truncate(trimmed.len() - 1);
+    }
+
+    text
+}
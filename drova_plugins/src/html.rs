@@ -0,0 +1,959 @@
+use std::collections::HashMap;
+
+use dalet::types::{Align, Body, HeadingLevel, ListStyle, Page, TableRows, Tag};
+use drova_sdk::requester::{Error, InputHandler, Metadata, Output, OutputHandler};
+use ego_tree::{NodeId, NodeRef};
+use scraper::{node::Element, ElementRef, Html, Node, Selector};
+use url::Url;
+
+use crate::utils::{has_block, is_block_among, resolve_link};
+
+/// Converts html documents to dalet
+#[derive(Default)]
+pub struct HtmlInput {
+    /// Keep only main content of page, dropping navigation, ads and other boilerplate
+    pub readability: bool,
+}
+
+impl InputHandler for HtmlInput {
+    fn process_text(&self, s: String, meta: &Metadata) -> Result<Page, Error> {
+        let html = Html::parse_document(&s);
+
+        let base = match select_attr(&html, "base[href]", "href") {
+            Some(href) => match &meta.url {
+                Some(url) => url.join(href).ok(),
+                None => Url::parse(href).ok(),
+            },
+            None => meta.url.clone(),
+        };
+
+        let title = select_attr(&html, "meta[property='og:title']", "content")
+            .map(String::from)
+            .or_else(|| select_text(&html, "title"))
+            .or_else(|| select_text(&html, "h1"))
+            .map(|title| collapse_whitespace(&title).trim().to_owned())
+            .filter(|title| !title.is_empty());
+
+        let description = select_attr(&html, "meta[name='description']", "content")
+            .or_else(|| select_attr(&html, "meta[property='og:description']", "content"))
+            .map(|description| collapse_whitespace(description).trim().to_owned())
+            .filter(|description| !description.is_empty());
+
+        let converter = HtmlConverter {
+            base,
+            readability: self.readability,
+        };
+
+        let root = match self.readability {
+            true => main_content(&html),
+            false => None,
+        }
+        .or_else(|| html.select(&selector("body")).next())
+        .unwrap_or_else(|| html.root_element());
+
+        Ok(Page {
+            title,
+            description,
+            body: converter.blocks(*root),
+            variables: None,
+        })
+    }
+
+    fn process_bytes(&self, _: Vec<u8>, _: &Metadata) -> Result<Page, Error> {
+        Err(Error::UnsupportedInput)
+    }
+}
+
+struct HtmlConverter {
+    base: Option<Url>,
+    readability: bool,
+}
+
+impl HtmlConverter {
+    /// Converts children of node, grouping inline content into paragraphs
+    fn blocks(&self, node: NodeRef<Node>) -> Vec<Tag> {
+        let mut blocks = Vec::new();
+        let mut inline = Vec::new();
+
+        for tag in self.children(node) {
+            if is_inline(&tag) {
+                inline.push(tag);
+            } else {
+                push_paragraph(&mut blocks, &mut inline);
+                blocks.push(tag);
+            }
+        }
+
+        push_paragraph(&mut blocks, &mut inline);
+
+        blocks
+    }
+
+    fn children(&self, node: NodeRef<Node>) -> Vec<Tag> {
+        node.children().flat_map(|child| self.node(child)).collect()
+    }
+
+    fn node(&self, node: NodeRef<Node>) -> Vec<Tag> {
+        let element = match node.value() {
+            Node::Text(text) => {
+                return vec![Tag::Element {
+                    body: collapse_whitespace(text).into(),
+                }]
+            }
+            Node::Element(element) => element,
+            _ => return vec![],
+        };
+
+        if self.skip(element) {
+            return vec![];
+        }
+
+        let name = element.name();
+
+        let tag = match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let heading = match name {
+                    "h1" => HeadingLevel::One,
+                    "h2" => HeadingLevel::Two,
+                    "h3" => HeadingLevel::Three,
+                    "h4" => HeadingLevel::Four,
+                    "h5" => HeadingLevel::Five,
+                    _ => HeadingLevel::Six,
+                };
+
+                let mut tags = anchor(element);
+                tags.push(Tag::Heading {
+                    body: text(node).trim().to_owned(),
+                    heading,
+                });
+
+                return tags;
+            }
+
+            "p" => match self.inlines(node) {
+                Some(body) => Tag::Paragraph { body: body.into() },
+                None => return vec![],
+            },
+
+            "a" => match element.attr("href").and_then(|href| self.link(href)) {
+                Some(dref) => {
+                    let body = self.inlines(node).map(Body::from);
+
+                    match element.attr("target") {
+                        Some("_blank") => Tag::Link { body, dref },
+                        _ => Tag::NavLink { body, dref },
+                    }
+                }
+                None => {
+                    let mut tags = anchor(element);
+                    tags.extend(self.children(node));
+                    return tags;
+                }
+            },
+
+            "img" => match element.attr("src").and_then(|src| self.link(src)) {
+                Some(src) => Tag::Image {
+                    src,
+                    alt: element.attr("alt").map(String::from),
+                },
+                None => return vec![],
+            },
+
+            "ul" | "ol" | "menu" => {
+                let mut body: Vec<Tag> = Vec::new();
+
+                // Content outside of list items is kept as separate items
+                for child in node.children() {
+                    let item = match child.value().as_element() {
+                        Some(element) if element.name() == "li" => self.blocks(child),
+                        Some(_) => self.node(child),
+                        None => continue,
+                    };
+
+                    if !item.is_empty() {
+                        body.push(Tag::Element { body: item.into() });
+                    }
+                }
+
+                if body.is_empty() {
+                    return vec![];
+                }
+
+                Tag::List {
+                    body,
+                    style: match name {
+                        "ol" => ListStyle::Decimal,
+                        _ => ListStyle::Disc,
+                    },
+                }
+            }
+
+            "dl" => Tag::List {
+                body: self
+                    .blocks(node)
+                    .into_iter()
+                    .map(|tag| Tag::Element {
+                        body: vec![tag].into(),
+                    })
+                    .collect(),
+                style: ListStyle::None,
+            },
+
+            "table" => {
+                let rows = self.table_rows(node);
+
+                if rows.is_empty() {
+                    return vec![];
+                }
+
+                Tag::Table { body: rows }
+            }
+
+            "pre" => Tag::Code {
+                body: node_text(node).trim_matches('\n').to_owned(),
+                language: language(node),
+            },
+
+            "code" => Tag::InlineCode {
+                body: node_text(node),
+                language: language(node),
+            },
+
+            "blockquote" => Tag::BlockQuote {
+                body: self.blocks(node).into(),
+            },
+
+            "strong" | "b" => Tag::Bold { body: text(node) },
+            "em" | "i" | "cite" | "dfn" => Tag::Italic { body: text(node) },
+            "s" | "del" | "strike" => Tag::Strikethrough { body: text(node) },
+            "sup" => Tag::Superscript { body: text(node) },
+            "sub" => Tag::Subscript { body: text(node) },
+            "kbd" | "samp" | "tt" | "var" => Tag::Mono { body: text(node) },
+
+            "br" => Tag::Element {
+                body: "\n".to_owned().into(),
+            },
+            "hr" => Tag::HorizontalBreak,
+
+            "details" => {
+                let title = node
+                    .children()
+                    .find(|child| {
+                        child
+                            .value()
+                            .as_element()
+                            .is_some_and(|element| element.name() == "summary")
+                    })
+                    .map(|summary| text(summary).trim().to_owned());
+
+                Tag::Disclosure {
+                    body: self.blocks(node).into(),
+                    title,
+                }
+            }
+            "summary" => return vec![],
+
+            "div" | "section" | "article" | "main" | "header" | "footer" | "nav" | "aside"
+            | "figure" | "figcaption" | "address" | "center" | "body" | "html" | "li" | "dd"
+            | "dt" | "fieldset" | "hgroup" | "search" => {
+                let mut tags = anchor(element);
+                tags.extend(self.blocks(node));
+                return tags;
+            }
+
+            _ => return self.children(node),
+        };
+
+        vec![tag]
+    }
+
+    /// Converts inline content of node, `None` if it is empty
+    fn inlines(&self, node: NodeRef<Node>) -> Option<Vec<Tag>> {
+        let mut tags = self.children(node);
+        trim_inline(&mut tags);
+
+        match tags.is_empty() {
+            true => None,
+            false => Some(tags),
+        }
+    }
+
+    fn table_rows(&self, node: NodeRef<Node>) -> Vec<TableRows> {
+        let mut rows = Vec::new();
+
+        for child in node.children() {
+            let Some(element) = child.value().as_element() else {
+                continue;
+            };
+
+            match element.name() {
+                "thead" | "tbody" | "tfoot" => {
+                    let header = element.name() == "thead";
+
+                    for row in self.table_rows(child) {
+                        match (header, row) {
+                            (true, TableRows::Default(cells)) => {
+                                rows.push(TableRows::Primary(cells))
+                            }
+                            (_, row) => rows.push(row),
+                        }
+                    }
+                }
+                "tr" => {
+                    let mut header = true;
+                    let mut cells = Vec::new();
+
+                    for cell in child.children() {
+                        let Some(element) = cell.value().as_element() else {
+                            continue;
+                        };
+
+                        match element.name() {
+                            "th" => {}
+                            "td" => header = false,
+                            _ => continue,
+                        }
+
+                        cells.push(Tag::Element {
+                            body: self.inlines(cell).unwrap_or_default().into(),
+                        });
+                    }
+
+                    if cells.is_empty() {
+                        continue;
+                    }
+
+                    match header {
+                        true => rows.push(TableRows::Primary(cells)),
+                        false => rows.push(TableRows::Default(cells)),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        rows
+    }
+
+    /// Resolves link against base url, dropping script links
+    fn link(&self, href: &str) -> Option<String> {
+        let href = href.trim();
+
+        if href.is_empty() || href.to_ascii_lowercase().starts_with("javascript:") {
+            return None;
+        }
+
+        Some(resolve_link(self.base.as_ref(), href))
+    }
+
+    fn skip(&self, element: &Element) -> bool {
+        if matches!(
+            element.name(),
+            "head"
+                | "script"
+                | "style"
+                | "noscript"
+                | "template"
+                | "iframe"
+                | "object"
+                | "embed"
+                | "svg"
+                | "canvas"
+                | "math"
+                | "input"
+                | "button"
+                | "select"
+                | "textarea"
+                | "dialog"
+        ) || element.attr("hidden").is_some()
+            || element.attr("aria-hidden") == Some("true")
+        {
+            return true;
+        }
+
+        self.readability && is_boilerplate(element)
+    }
+}
+
+/// Finds main content of page, like readability does
+fn main_content(html: &Html) -> Option<ElementRef<'_>> {
+    let articles: Vec<ElementRef> = html.select(&selector("article")).collect();
+
+    if articles.len() == 1 {
+        return articles.into_iter().next();
+    }
+
+    if let Some(main) = html.select(&selector("main, [role='main']")).next() {
+        return Some(main);
+    }
+
+    let mut scores: HashMap<NodeId, f64> = HashMap::new();
+
+    for paragraph in html.select(&selector("p, pre, td, blockquote")) {
+        let text = collapse_whitespace(&paragraph.text().collect::<String>());
+        let len = text.trim().chars().count();
+
+        if len < 25 {
+            continue;
+        }
+
+        let score = 1.0 + text.matches(',').count() as f64 + (len as f64 / 100.0).min(3.0);
+
+        let mut ancestors = paragraph
+            .ancestors()
+            .filter(|node| node.value().is_element());
+
+        if let Some(parent) = ancestors.next() {
+            *scores.entry(parent.id()).or_default() += score;
+        }
+        if let Some(grandparent) = ancestors.next() {
+            *scores.entry(grandparent.id()).or_default() += score / 2.0;
+        }
+    }
+
+    scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let element = ElementRef::wrap(html.tree.get(id)?)?;
+
+            if is_boilerplate(element.value()) {
+                return None;
+            }
+
+            Some((element, score * (1.0 - link_density(element))))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(element, _)| element)
+}
+
+fn link_density(element: ElementRef) -> f64 {
+    let len = element.text().map(str::len).sum::<usize>();
+
+    if len == 0 {
+        return 0.0;
+    }
+
+    let links = element
+        .select(&selector("a"))
+        .flat_map(|link| link.text())
+        .map(str::len)
+        .sum::<usize>();
+
+    links as f64 / len as f64
+}
+
+const NEGATIVE: &[&str] = &[
+    "ad-",
+    "ads",
+    "advert",
+    "banner",
+    "breadcrumb",
+    "comment",
+    "cookie",
+    "footer",
+    "masthead",
+    "menu",
+    "modal",
+    "nav",
+    "newsletter",
+    "popup",
+    "promo",
+    "related",
+    "share",
+    "sidebar",
+    "social",
+    "sponsor",
+    "subscribe",
+    "widget",
+];
+
+const POSITIVE: &[&str] = &[
+    "article", "body", "content", "entry", "main", "post", "story",
+];
+
+fn is_boilerplate(element: &Element) -> bool {
+    if matches!(
+        element.name(),
+        "nav" | "header" | "footer" | "aside" | "form"
+    ) {
+        return true;
+    }
+
+    if matches!(
+        element.attr("role"),
+        Some("navigation" | "banner" | "contentinfo" | "complementary" | "search")
+    ) {
+        return true;
+    }
+
+    let names = format!(
+        "{} {}",
+        element.id().unwrap_or_default(),
+        element.attr("class").unwrap_or_default()
+    )
+    .to_ascii_lowercase();
+
+    NEGATIVE.iter().any(|pattern| names.contains(pattern))
+        && !POSITIVE.iter().any(|pattern| names.contains(pattern))
+}
+
+fn anchor(element: &Element) -> Vec<Tag> {
+    match element.id().or_else(|| element.attr("name")) {
+        Some(id) if !id.is_empty() => vec![Tag::Anchor { id: id.into() }],
+        _ => vec![],
+    }
+}
+
+fn language(node: NodeRef<Node>) -> Option<String> {
+    let code = ElementRef::wrap(node)?;
+
+    std::iter::once(code)
+        .chain(code.select(&selector("code")))
+        .flat_map(|element| element.value().classes())
+        .find_map(|class| {
+            class
+                .strip_prefix("language-")
+                .or_else(|| class.strip_prefix("lang-"))
+        })
+        .map(String::from)
+}
+
+fn is_inline(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Element {
+            body: Body::Text(_)
+        } | Tag::Link { .. }
+            | Tag::NavLink { .. }
+            | Tag::Button { .. }
+            | Tag::NavButton { .. }
+            | Tag::Image { .. }
+            | Tag::Bold { .. }
+            | Tag::Italic { .. }
+            | Tag::Strikethrough { .. }
+            | Tag::Superscript { .. }
+            | Tag::Subscript { .. }
+            | Tag::Mono { .. }
+            | Tag::InlineCode { .. }
+            | Tag::FootLink { .. }
+            | Tag::Anchor { .. }
+    )
+}
+
+fn push_paragraph(blocks: &mut Vec<Tag>, inline: &mut Vec<Tag>) {
+    trim_inline(inline);
+
+    if inline.iter().all(|tag| matches!(tag, Tag::Anchor { .. })) {
+        blocks.append(inline);
+    } else {
+        blocks.push(Tag::Paragraph {
+            body: std::mem::take(inline).into(),
+        });
+    }
+}
+
+/// Removes leading and trailing whitespace of inline content
+fn trim_inline(tags: &mut Vec<Tag>) {
+    let is_blank = |tag: &Tag| match tag {
+        Tag::Element {
+            body: Body::Text(text),
+        } => text.trim().is_empty(),
+        _ => false,
+    };
+
+    while tags.first().is_some_and(is_blank) {
+        tags.remove(0);
+    }
+    while tags.last().is_some_and(is_blank) {
+        tags.pop();
+    }
+
+    if let Some(Tag::Element {
+        body: Body::Text(text),
+    }) = tags.first_mut()
+    {
+        *text = text.trim_start().to_owned();
+    }
+    if let Some(Tag::Element {
+        body: Body::Text(text),
+    }) = tags.last_mut()
+    {
+        *text = text.trim_end().to_owned();
+    }
+}
+
+/// Text of node with collapsed whitespace
+fn text(node: NodeRef<Node>) -> String {
+    collapse_whitespace(&node_text(node))
+}
+
+/// Text of node as is
+fn node_text(node: NodeRef<Node>) -> String {
+    node.descendants()
+        .filter_map(|node| match node.value() {
+            Node::Text(text) => Some(&**text),
+            Node::Element(element) if element.name() == "br" => Some("\n"),
+            _ => None,
+        })
+        .collect()
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut space = false;
+
+    for c in s.chars() {
+        if c.is_whitespace() {
+            space = true;
+        } else {
+            if space {
+                out.push(' ');
+                space = false;
+            }
+            out.push(c);
+        }
+    }
+
+    if space {
+        out.push(' ');
+    }
+
+    out
+}
+
+fn selector(s: &str) -> Selector {
+    Selector::parse(s).expect("valid selector")
+}
+
+fn select_attr<'a>(html: &'a Html, s: &str, attr: &str) -> Option<&'a str> {
+    html.select(&selector(s))
+        .find_map(|element| element.attr(attr))
+}
+
+fn select_text(html: &Html, s: &str) -> Option<String> {
+    html.select(&selector(s))
+        .next()
+        .map(|element| element.text().collect())
+}
+
+pub struct HtmlOutput;
+
+impl OutputHandler for HtmlOutput {
+    fn render(&self, page: &Page) -> Result<Output, Error> {
+        let vars = page.variables.as_deref().unwrap_or_default();
+        let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+
+        if let Some(title) = &page.title {
+            out.push_str(&format!("<title>{}</title>\n", escape(title)));
+        }
+
+        if let Some(description) = &page.description {
+            out.push_str(&format!(
+                "<meta name=\"description\" content=\"{}\">\n",
+                escape(description)
+            ));
+        }
+
+        out.push_str("</head>\n<body>\n");
+
+        for tag in &page.body {
+            render_among(&mut out, tag, &page.body, vars);
+            out.push('\n');
+        }
+
+        out.push_str("</body>\n</html>\n");
+
+        Ok(Output::Text(out))
+    }
+}
+
+fn render_tag(out: &mut String, tag: &Tag, vars: &[String]) {
+    match tag {
+        Tag::Element { body } => render_body(out, body, vars),
+
+        Tag::Heading { body, heading } => {
+            let level = heading_level(heading);
+            out.push_str(&format!("<h{level}>{}</h{level}>", escape(body)));
+        }
+
+        // Blocks are not allowed inside `<p>`
+        Tag::Paragraph {
+            body: Body::Tags(tags),
+        } if has_block(tags) => render_block(out, "<div>", tags, vars),
+        Tag::Paragraph { body } => wrap(out, "p", body, vars),
+
+        Tag::Link { body, dref } => render_link(out, body, dref, "", true, vars),
+        Tag::NavLink { body, dref } => render_link(out, body, dref, "", false, vars),
+        Tag::Button { body, dref } => render_link(out, body, dref, "button", true, vars),
+        Tag::NavButton { body, dref } => render_link(out, body, dref, "button", false, vars),
+
+        Tag::Image { src, alt } => out.push_str(&format!(
+            "<img src=\"{}\" alt=\"{}\">",
+            escape(safe_url(src)),
+            escape(alt.as_deref().unwrap_or_default())
+        )),
+
+        Tag::Table { body } => {
+            out.push_str("<table>\n");
+
+            for row in body {
+                let (cells, cell_tag) = match row {
+                    TableRows::Primary(cells) => (cells, "th"),
+                    TableRows::Default(cells) => (cells, "td"),
+                };
+
+                out.push_str("<tr>");
+
+                for cell in cells {
+                    out.push_str(&format!("<{cell_tag}>"));
+                    render_tag(out, cell, vars);
+                    out.push_str(&format!("</{cell_tag}>"));
+                }
+
+                out.push_str("</tr>\n");
+            }
+
+            out.push_str("</table>");
+        }
+
+        Tag::List { body, style } => {
+            let open = match style {
+                ListStyle::Disc => "<ul>",
+                ListStyle::Decimal => "<ol>",
+                ListStyle::None => "<ul style=\"list-style: none\">",
+            };
+            let close = match style {
+                ListStyle::Decimal => "</ol>",
+                _ => "</ul>",
+            };
+
+            out.push_str(open);
+            out.push('\n');
+
+            for item in body {
+                out.push_str("<li>");
+                render_tag(out, item, vars);
+                out.push_str("</li>\n");
+            }
+
+            out.push_str(close);
+        }
+
+        Tag::Bold { body } => wrap_text(out, "strong", body),
+        Tag::Italic { body } => wrap_text(out, "em", body),
+        Tag::Strikethrough { body } => wrap_text(out, "s", body),
+        Tag::Superscript { body } => wrap_text(out, "sup", body),
+        Tag::Subscript { body } => wrap_text(out, "sub", body),
+        Tag::Mono { body } => wrap_text(out, "pre", body),
+
+        Tag::FootLink { footnote } => out.push_str(&format!(
+            "<sup><a href=\"#footnote-{footnote}\" id=\"footlink-{footnote}\">[{}]</a></sup>",
+            footnote + 1
+        )),
+
+        Tag::FootNote { body, footnote } => out.push_str(&format!(
+            "<aside id=\"footnote-{footnote}\" role=\"doc-footnote\"><a href=\"#footlink-{footnote}\">[{}]</a> {}</aside>",
+            footnote + 1,
+            escape(body)
+        )),
+
+        Tag::Anchor { id } => out.push_str(&format!("<a id=\"{}\"></a>", escape(id))),
+
+        Tag::BlockQuote { body } => wrap(out, "blockquote", body, vars),
+
+        Tag::Code { body, language } => out.push_str(&format!(
+            "<pre><code{}>{}</code></pre>",
+            language_class(language),
+            escape(body)
+        )),
+
+        Tag::InlineCode { body, language } => out.push_str(&format!(
+            "<code{}>{}</code>",
+            language_class(language),
+            escape(body)
+        )),
+
+        Tag::Block { body } => render_block(out, "<div>", body, vars),
+
+        Tag::Flex {
+            body,
+            wrap,
+            align_x,
+            align_y,
+        } => {
+            let mut style = String::from("display: flex");
+
+            if *wrap {
+                style.push_str("; flex-wrap: wrap");
+            }
+            if let Some(align) = align_x {
+                style.push_str(&format!("; justify-content: {}", align_value(align)));
+            }
+            if let Some(align) = align_y {
+                style.push_str(&format!("; align-items: {}", align_value(align)));
+            }
+
+            render_block(out, &format!("<div style=\"{style}\">"), body, vars);
+        }
+
+        Tag::Grid {
+            body,
+            align_x,
+            align_y,
+        } => {
+            let mut style = String::from("display: grid");
+
+            if let Some(align) = align_x {
+                style.push_str(&format!("; justify-items: {}", align_value(align)));
+            }
+            if let Some(align) = align_y {
+                style.push_str(&format!("; align-items: {}", align_value(align)));
+            }
+
+            render_block(out, &format!("<div style=\"{style}\">"), body, vars);
+        }
+
+        Tag::Disclosure { body, title } => {
+            out.push_str("<details>");
+
+            if let Some(title) = title {
+                out.push_str(&format!("<summary>{}</summary>", escape(title)));
+            }
+
+            render_body(out, body, vars);
+            out.push_str("</details>");
+        }
+
+        Tag::Carousel { body } => render_block(out, "<div>", body, vars),
+
+        Tag::Variable { idx } => {
+            if let Some(var) = usize::try_from(*idx).ok().and_then(|idx| vars.get(idx)) {
+                out.push_str(&escape(var));
+            }
+        }
+
+        Tag::HorizontalBreak => out.push_str("<hr>"),
+    }
+}
+
+/// Mono with inline siblings is written inline, e.g. `<kbd>` inside paragraph
+fn render_among(out: &mut String, tag: &Tag, siblings: &[Tag], vars: &[String]) {
+    match tag {
+        Tag::Mono { body } if !is_block_among(tag, siblings) => wrap_text(out, "code", body),
+        tag => render_tag(out, tag, vars),
+    }
+}
+
+fn render_body(out: &mut String, body: &Body, vars: &[String]) {
+    match body {
+        Body::Text(text) => out.push_str(&escape(text)),
+        Body::Tags(tags) => {
+            for tag in tags {
+                render_among(out, tag, tags, vars);
+            }
+        }
+    }
+}
+
+fn render_block(out: &mut String, open: &str, body: &[Tag], vars: &[String]) {
+    out.push_str(open);
+
+    for tag in body {
+        render_among(out, tag, body, vars);
+    }
+
+    out.push_str("</div>");
+}
+
+fn render_link(
+    out: &mut String,
+    body: &Option<Body>,
+    dref: &str,
+    class: &str,
+    new_tab: bool,
+    vars: &[String],
+) {
+    out.push_str(&format!("<a href=\"{}\"", escape(safe_url(dref))));
+
+    if !class.is_empty() {
+        out.push_str(&format!(" class=\"{class}\""));
+    }
+    if new_tab {
+        out.push_str(" target=\"_blank\" rel=\"noopener noreferrer\"");
+    }
+
+    out.push('>');
+
+    match body {
+        Some(body) => render_body(out, body, vars),
+        None => out.push_str(&escape(dref)),
+    }
+
+    out.push_str("</a>");
+}
+
+fn wrap(out: &mut String, name: &str, body: &Body, vars: &[String]) {
+    out.push_str(&format!("<{name}>"));
+    render_body(out, body, vars);
+    out.push_str(&format!("</{name}>"));
+}
+
+fn wrap_text(out: &mut String, name: &str, text: &str) {
+    out.push_str(&format!("<{name}>{}</{name}>", escape(text)));
+}
+
+fn heading_level(heading: &HeadingLevel) -> u8 {
+    heading.clone() as u8
+}
+
+fn language_class(language: &Option<String>) -> String {
+    match language {
+        Some(language) => format!(" class=\"language-{}\"", escape(language)),
+        None => String::new(),
+    }
+}
+
+fn align_value(align: &Align) -> &'static str {
+    match align {
+        Align::Start => "start",
+        Align::Center => "center",
+        Align::End => "end",
+    }
+}
+
+/// Drops urls that would execute scripts when followed
+fn safe_url(url: &str) -> &str {
+    let scheme = url
+        .split_once(':')
+        .map(|(scheme, _)| {
+            scheme
+                .chars()
+                .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+                .collect::<String>()
+                .to_ascii_lowercase()
+        })
+        .unwrap_or_default();
+
+    match scheme.as_str() {
+        "javascript" | "vbscript" => "#",
+        _ => url,
+    }
+}
+
+/// Escapes text for use in html body and quoted attributes
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
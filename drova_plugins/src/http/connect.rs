@@ -0,0 +1,70 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tower_layer::Layer;
+use tower_service::Service;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+tokio::task_local! {
+    /// Connect timeout of request being sent, read when client opens connection
+    static CONNECT_TIMEOUT: Option<Duration>;
+}
+
+/// Runs sending of request with its own connect timeout
+pub(super) async fn with_connect_timeout<F: Future>(
+    timeout: Option<Duration>,
+    fut: F,
+) -> F::Output {
+    CONNECT_TIMEOUT.scope(timeout, fut).await
+}
+
+/// Applies connect timeout of request to connections opened for it.
+/// Client connector is shared, so timeout is passed through task local
+#[derive(Clone)]
+pub(super) struct ConnectTimeoutLayer;
+
+impl<S> Layer<S> for ConnectTimeoutLayer {
+    type Service = ConnectTimeout<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConnectTimeout(inner)
+    }
+}
+
+#[derive(Clone)]
+pub(super) struct ConnectTimeout<S>(S);
+
+impl<S, R> Service<R> for ConnectTimeout<S>
+where
+    S: Service<R, Error = BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let timeout = CONNECT_TIMEOUT.try_with(|timeout| *timeout).ok().flatten();
+        let fut = self.0.call(req);
+
+        Box::pin(async move {
+            match timeout {
+                // Timed out io error is reported by reqwest as timeout
+                Some(timeout) => tokio::time::timeout(timeout, fut)
+                    .await
+                    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))?,
+                None => fut.await,
+            }
+        })
+    }
+}
@@ -1 +1,2 @@
+mod connect;
 pub mod protocol;
@@ -1,63 +1,382 @@
+use std::{
+    error::Error as StdError,
+    fmt, io,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
 use async_trait::async_trait;
-use drova_sdk::requester::{Error, ProtocolHandler, Response, ResponseData};
+use drova_sdk::requester::{
+    BodyStream, Error, FetchOptions, Metadata, ProtocolHandler, Response, ResponseData,
+};
+use futures_util::TryStreamExt;
 use mime::Mime;
-use reqwest::header::CONTENT_TYPE;
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::{
+        HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, LAST_MODIFIED, LOCATION, RETRY_AFTER,
+    },
+    redirect::Policy,
+    tls, Certificate, Client, ClientBuilder, Proxy, StatusCode,
+};
+use tokio::io::AsyncReadExt;
+use tokio_util::io::StreamReader;
 
-use crate::utils::mime_to_str;
+use super::connect::{with_connect_timeout, ConnectTimeoutLayer};
+use crate::utils::{decode_stream, decode_text, is_text, mime_params, mime_to_str};
 
-pub struct HttpProtocol;
+const USER_AGENT: &str = concat!("drova/", env!("CARGO_PKG_VERSION"));
 
-#[async_trait]
-impl ProtocolHandler for HttpProtocol {
-    async fn fetch(&self, url: &url::Url) -> Result<Response, Error> {
-        let res = reqwest::get(url.to_string())
-            .await
-            .map_err(match_reqwest_error)?;
-
-        let ty = mime_to_str(
-            {
-                match res.headers().get(CONTENT_TYPE) {
-                    Some(header) => match header.to_str() {
-                        Ok(mime) => mime.to_owned(),
-                        Err(_) => "text/plain".to_owned(),
+/// Http/s protocol, sharing one client (and its connection pool) between requests
+#[derive(Clone)]
+pub struct HttpProtocol {
+    client: Client,
+}
+
+impl HttpProtocol {
+    pub fn builder() -> HttpProtocolBuilder {
+        HttpProtocolBuilder::default()
+    }
+
+    /// Use already configured client.
+    /// It should not follow redirects, see [`HttpProtocolBuilder::redirect`].
+    /// [`FetchOptions::connect_timeout`] is not applied to it
+    pub fn with_client(client: Client) -> Self {
+        Self { client }
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl Default for HttpProtocol {
+    fn default() -> Self {
+        Self::builder()
+            .build()
+            .expect("TLS backend can not be initialized")
+    }
+}
+
+pub struct HttpProtocolBuilder {
+    client: ClientBuilder,
+    headers: HeaderMap,
+}
+
+impl Default for HttpProtocolBuilder {
+    fn default() -> Self {
+        Self {
+            client: Client::builder()
+                .redirect(Policy::none())
+                .user_agent(USER_AGENT)
+                .dns_resolver(Arc::new(SystemResolver))
+                .connector_layer(ConnectTimeoutLayer),
+            headers: HeaderMap::new(),
+        }
+    }
+}
+
+impl HttpProtocolBuilder {
+    /// Timeout of whole request, from connecting until body is read
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.timeout(timeout);
+        self
+    }
+
+    /// Connect timeout of all requests, [`FetchOptions::connect_timeout`] is applied too
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.connect_timeout(timeout);
+        self
+    }
+
+    /// Timeout between reads of response
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.read_timeout(timeout);
+        self
+    }
+
+    /// How long idle connections are kept in pool
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.pool_idle_timeout(timeout);
+        self
+    }
+
+    /// Default is `drova/<version>`
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.client = self.client.user_agent(user_agent);
+        self
+    }
+
+    /// Header sent with every request
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Headers sent with every request, extends ones added before
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.client = self.client.proxy(proxy);
+        self
+    }
+
+    /// Default is [`Policy::none`], so redirects are followed by requester,
+    /// which reports final url and can change scheme
+    pub fn redirect(mut self, policy: Policy) -> Self {
+        self.client = self.client.redirect(policy);
+        self
+    }
+
+    /// Trust additional root certificate
+    pub fn root_certificate(mut self, cert: Certificate) -> Self {
+        self.client = self.client.add_root_certificate(cert);
+        self
+    }
+
+    pub fn min_tls_version(mut self, version: tls::Version) -> Self {
+        self.client = self.client.min_tls_version(version);
+        self
+    }
+
+    /// Accept invalid and self-signed certificates. Dangerous, use only for testing
+    pub fn accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.client = self.client.danger_accept_invalid_certs(accept);
+        self
+    }
+
+    /// Refuse plain http urls
+    pub fn https_only(mut self, enabled: bool) -> Self {
+        self.client = self.client.https_only(enabled);
+        self
+    }
+
+    pub fn build(self) -> Result<HttpProtocol, Error> {
+        let client = self
+            .client
+            .default_headers(self.headers)
+            .build()
+            .map_err(|e| Error::IoError(e.to_string()))?;
+
+        Ok(HttpProtocol { client })
+    }
+}
+
+impl HttpProtocol {
+    async fn response(
+        &self,
+        url: &url::Url,
+        opts: &FetchOptions,
+        stream: bool,
+    ) -> Result<Response, Error> {
+        let send = with_connect_timeout(opts.connect_timeout, self.client.get(url.as_str()).send());
+        let res = opts.first_byte(send).await?.map_err(match_reqwest_error)?;
+
+        if let Some(max) = opts.max_header_size {
+            let size: usize = res
+                .headers()
+                .iter()
+                .map(|(name, value)| name.as_str().len() + value.len() + 4)
+                .sum();
+
+            if size > max {
+                return Err(Error::ExceededHeaderSize);
+            }
+        }
+
+        if res.status().is_redirection() {
+            if let Some(location) = res.headers().get(LOCATION) {
+                let location = location.to_str().map_err(|_| Error::InvalidUrl)?;
+
+                return Ok(Response {
+                    data: ResponseData::Redirect {
+                        url: url.join(location)?,
+                        permanent: matches!(
+                            res.status(),
+                            StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+                        ),
                     },
-                    None => "text/plain".to_owned(),
-                }
+                    ty: String::new(),
+                    meta: Metadata::default(),
+                });
+            }
+        }
+
+        if !res.status().is_success() {
+            return Err(match_status(res.status(), retry_after(res.headers())));
+        }
+
+        let mime = match res.headers().get(CONTENT_TYPE) {
+            Some(header) => header.to_str().unwrap_or("text/plain"),
+            None => "text/plain",
+        }
+        .parse::<Mime>()
+        .map_err(|e| Error::InvalidMimeType(e.to_string()))?;
+
+        let ty = mime_to_str(&mime);
+        let meta = Metadata {
+            url: Some(res.url().clone()),
+            params: mime_params(&mime),
+            headers: res
+                .headers()
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.as_str().to_owned(), value.to_str().ok()?.to_owned()))
+                })
+                .collect(),
+            last_modified: res
+                .headers()
+                .get(LAST_MODIFIED)
+                .and_then(|date| httpdate::parse_http_date(date.to_str().ok()?).ok()),
+        };
+
+        if let (Some(max), Some(len)) = (opts.max_body_size, res.content_length()) {
+            if len > max as u64 {
+                return Err(Error::ExceededBodySize);
             }
-            .parse::<Mime>()
-            .map_err(|e| Error::InvalidMimeType(e.to_string()))?,
-        );
+        }
+
+        let body = res
+            .bytes_stream()
+            .map_err(|e| io::Error::other(match_reqwest_error(e)));
+        let body = BodyStream::new(StreamReader::new(body));
+
+        if stream {
+            // Text is decoded to UTF-8, which requester expects
+            let text = is_text(&mime);
+            let body = match text {
+                true => decode_stream(body, &mime),
+                false => body,
+            };
+
+            return Ok(Response {
+                data: ResponseData::Stream { body, text },
+                ty,
+                meta,
+            });
+        }
+
+        let mut bytes = Vec::new();
+        body.max_size(opts.max_body_size)
+            .read_to_end(&mut bytes)
+            .await?;
 
-        match ty.starts_with("text") {
+        match is_text(&mime) {
             true => Ok(Response {
-                data: ResponseData::TextOutput(res.text().await.map_err(match_reqwest_error)?),
+                data: ResponseData::TextOutput(decode_text(&bytes, &mime)?),
                 ty,
+                meta,
             }),
             false => Ok(Response {
-                data: ResponseData::BitsOutput(
-                    res.bytes().await.map_err(match_reqwest_error)?.to_vec(),
-                ),
+                data: ResponseData::BitsOutput(bytes),
                 ty,
+                meta,
             }),
         }
     }
 }
 
+#[async_trait]
+impl ProtocolHandler for HttpProtocol {
+    async fn fetch(&self, url: &url::Url, opts: &FetchOptions) -> Result<Response, Error> {
+        self.response(url, opts, false).await
+    }
+
+    async fn fetch_stream(&self, url: &url::Url, opts: &FetchOptions) -> Result<Response, Error> {
+        self.response(url, opts, true).await
+    }
+}
+
+/// Seconds to wait from `Retry-After`, given either as seconds or as date
+fn retry_after(headers: &HeaderMap) -> Option<u64> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    match value.parse() {
+        Ok(secs) => Some(secs),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            let wait = date.duration_since(SystemTime::now()).unwrap_or_default();
+
+            Some(wait.as_secs())
+        }
+    }
+}
+
+fn match_status(status: StatusCode, retry_after: Option<u64>) -> Error {
+    match status.as_u16() {
+        400 => Error::BadRequest,
+        401 => Error::NotAuthorized,
+        403 => Error::Forbidden,
+        404 => Error::NotFound,
+        405 => Error::MethodNotAllowed,
+        406 => Error::NotAcceptable,
+        410 => Error::Gone,
+        429 => Error::TooManyRequests(retry_after),
+        500 => Error::Failure,
+        502..=504 => Error::ServerUnavailable(retry_after),
+        s => Error::UnknownStatus(s.into()),
+    }
+}
+
 fn match_reqwest_error(e: reqwest::Error) -> Error {
-    match e.status() {
-        Some(s) => match s.as_u16() {
-            400 => Error::BadRequest,
-            401 => Error::NotAuthorized,
-            403 => Error::Forbidden,
-            404 => Error::NotFound,
-            405 => Error::MethodNotAllowed,
-            406 => Error::NotAcceptable,
-            410 => Error::Gone,
-            429 => Error::TooManyRequests,
-            500 => Error::Failure,
-            503 => Error::ServerUnavailable,
-            s => Error::UnknownStatus(s.into()),
-        },
-        None => Error::InvalidStatus,
+    if let Some(status) = e.status() {
+        return match_status(status, None);
+    }
+
+    if e.is_timeout() {
+        return Error::Timeout;
+    }
+
+    let mut source = e.source();
+
+    while let Some(err) = source {
+        if err.is::<DnsError>() {
+            return Error::DnsFailed;
+        }
+
+        if err.is::<native_tls::Error>() {
+            return Error::TlsFailed;
+        }
+
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            return Error::IoError(err.to_string());
+        }
+
+        source = err.source();
+    }
+
+    Error::IoError(e.to_string())
+}
+
+/// System resolver, with failures marked so they are reported as [`Error::DnsFailed`]
+struct SystemResolver;
+
+impl Resolve for SystemResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0))
+                .await
+                .map_err(DnsError)?;
+
+            Ok(Box::new(addrs.collect::<Vec<_>>().into_iter()) as Addrs)
+        })
+    }
+}
+
+#[derive(Debug)]
+struct DnsError(io::Error);
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "dns error: {}", self.0)
+    }
+}
+
+impl StdError for DnsError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.0)
     }
 }
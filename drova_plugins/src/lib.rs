@@ -1,23 +1,51 @@
+use std::sync::Arc;
+
+use daletpack::{DaletpackInput, DaletpackOutput};
 use drova_sdk::requester::RequesterBuilder;
-use gemini::{gemtext::GemtextInput, protocol::GeminiProtocol};
+use gemini::{
+    gemtext::{GemtextInput, GemtextOutput},
+    protocol::GeminiProtocol,
+};
+use gopher::{gophermap::GophermapInput, protocol::GopherProtocol};
+use html::{HtmlInput, HtmlOutput};
 use http::protocol::HttpProtocol;
-use markdown::MarkdownInput;
-use text::TextInput;
+use markdown::{MarkdownInput, MarkdownOutput};
+use text::{TextInput, TextOutput};
 
 mod utils;
 
+pub mod daletpack;
 pub mod gemini;
+pub mod gopher;
+pub mod html;
 pub mod http;
 pub mod markdown;
 pub mod text;
 
 pub fn requester_plugins(app: RequesterBuilder) -> RequesterBuilder {
-    app.protocol("http", &HttpProtocol)
-        .protocol("https", &HttpProtocol)
-        .protocol("gemini", &GeminiProtocol)
-        .input("text/gemini", &GemtextInput)
-        .input("text/markdown", &MarkdownInput)
-        .input("text/x-markdown", &MarkdownInput)
-        .input("text/plain", &TextInput)
-        .input("text/*", &TextInput)
+    let http = Arc::new(HttpProtocol::default());
+    let markdown = Arc::new(MarkdownInput);
+    let text = Arc::new(TextInput);
+    let html = Arc::new(HtmlInput::default());
+    let gemtext = Arc::new(GemtextInput);
+
+    app.protocol("http", http.clone())
+        .protocol("https", http)
+        .protocol("gemini", Arc::new(GeminiProtocol::default()))
+        .protocol("gopher", Arc::new(GopherProtocol))
+        .input("application/daletpack", Arc::new(DaletpackInput))
+        .input("text/gemini", gemtext.clone())
+        .input("text/gophermap", Arc::new(GophermapInput))
+        .input("text/html", html.clone())
+        .input("application/xhtml+xml", html)
+        .input("text/markdown", markdown.clone())
+        .input("text/x-markdown", markdown)
+        .input("text/plain", text.clone())
+        .input("text/*", text)
+        .line_input("text/gemini", gemtext)
+        .output("application/daletpack", Arc::new(DaletpackOutput))
+        .output("text/html", Arc::new(HtmlOutput))
+        .output("text/gemini", Arc::new(GemtextOutput))
+        .output("text/markdown", Arc::new(MarkdownOutput))
+        .output("text/plain", Arc::new(TextOutput::default()))
 }
@@ -1,16 +1,32 @@
 use std::collections::HashMap;
 
-use dalet::types::{ListStyle, Page, TableRows, Tag, Text};
-use drova_sdk::requester::{Error, InputHandler};
-use markdown::mdast::Node;
+use dalet::types::{Body, ListStyle, Page, TableRows, Tag, Text};
+use drova_sdk::requester::{Error, InputHandler, Metadata, Output, OutputHandler};
+use markdown::{mdast::Node, ParseOptions};
 use url::Url;
 
+use crate::utils::{is_block, is_block_among, resolve_link};
+
 pub struct MarkdownInput;
 
 impl InputHandler for MarkdownInput {
-    fn process_text(&self, s: String, _: Option<&Url>) -> Result<Page, Error> {
-        let ast = markdown::to_mdast(&s, &markdown::ParseOptions::gfm())
-            .map_err(|_| Error::InvalidSyntax)?;
+    fn process_text(&self, s: String, meta: &Metadata) -> Result<Page, Error> {
+        // Markdown variant (RFC 7763), GFM by default
+        let options = match meta.param("variant") {
+            Some(v)
+                if v.eq_ignore_ascii_case("commonmark") || v.eq_ignore_ascii_case("original") =>
+            {
+                ParseOptions::default()
+            }
+            Some(v) if v.eq_ignore_ascii_case("mdx") => ParseOptions::mdx(),
+            _ => ParseOptions::gfm(),
+        };
+
+        let mut ast = markdown::to_mdast(&s, &options).map_err(|_| Error::InvalidSyntax)?;
+
+        if let Some(base) = &meta.url {
+            resolve_links(&mut ast, base);
+        }
 
         let mut page: Page = Page {
             title: None,
@@ -47,24 +63,34 @@ impl InputHandler for MarkdownInput {
         Ok(page)
     }
 
-    fn process_bytes(&self, _: Vec<u8>, _: Option<&Url>) -> Result<Page, Error> {
+    fn process_bytes(&self, _: Vec<u8>, _: &Metadata) -> Result<Page, Error> {
         Err(Error::UnsupportedInput)
     }
 }
 
+/// Resolves urls of links and images against base url
+fn resolve_links(node: &mut Node, base: &Url) {
+    match node {
+        Node::Link(n) => n.url = resolve_link(Some(base), &n.url),
+        Node::Image(n) => n.url = resolve_link(Some(base), &n.url),
+        _ => {}
+    }
+
+    for child in node.children_mut().into_iter().flatten() {
+        resolve_links(child, base);
+    }
+}
+
 fn manage_foot_links(
     foot_count: &mut u64,
     footnotes: &mut HashMap<String, u64>,
     nodes: &Vec<Node>,
 ) {
     for node in nodes {
-        match node {
-            Node::FootnoteDefinition(n) => {
-                footnotes.insert(n.identifier.clone(), *foot_count);
+        if let Node::FootnoteDefinition(n) = node {
+            footnotes.insert(n.identifier.clone(), *foot_count);
 
-                *foot_count += 1;
-            }
-            _ => {}
+            *foot_count += 1;
         }
     }
 }
@@ -117,7 +143,7 @@ fn convert_node(
         }),
 
         Node::Code(n) => Ok(Tag::Code {
-            body: n.value.into(),
+            body: n.value,
             language: n.lang,
         }),
 
@@ -128,7 +154,7 @@ fn convert_node(
         Node::Definition(n) => Ok(Tag::Anchor { id: n.identifier }),
 
         Node::FootnoteDefinition(n) => Ok(Tag::FootNote {
-            body: nodes_to_text(n.children)?.into(),
+            body: nodes_to_text(n.children)?,
             footnote: *footnotes.get(&n.identifier).unwrap(),
         }),
 
@@ -142,7 +168,7 @@ fn convert_node(
         }),
 
         Node::List(n) => Ok(Tag::List {
-            body: convert_nodes(page, foot_count, footnotes, n.children)?.into(),
+            body: convert_nodes(page, foot_count, footnotes, n.children)?,
             style: if n.ordered {
                 ListStyle::Decimal
             } else {
@@ -155,23 +181,16 @@ fn convert_node(
 
         Node::Table(n) => {
             let mut rows: Vec<TableRows> = vec![];
-            let mut primary_been = false;
 
             for row in n.children {
-                match row {
-                    Node::TableCell(n) => {
-                        if primary_been {
-                            rows.push(TableRows::Default(
-                                convert_nodes(page, foot_count, footnotes, n.children)?.into(),
-                            ));
-                        } else {
-                            primary_been = true;
-                            rows.push(TableRows::Primary(
-                                convert_nodes(page, foot_count, footnotes, n.children)?.into(),
-                            ));
-                        }
+                if let Node::TableRow(row) = row {
+                    let cells = convert_nodes(page, foot_count, footnotes, row.children)?;
+
+                    if rows.is_empty() {
+                        rows.push(TableRows::Primary(cells));
+                    } else {
+                        rows.push(TableRows::Default(cells));
                     }
-                    _ => {}
                 }
             }
 
@@ -281,3 +300,342 @@ fn nodes_to_text(nodes: Vec<Node>) -> Result<Text, Error> {
 
     Ok(output)
 }
+
+pub struct MarkdownOutput;
+
+impl OutputHandler for MarkdownOutput {
+    fn render(&self, page: &Page) -> Result<Output, Error> {
+        let vars = page.variables.as_deref().unwrap_or_default();
+
+        let mut out = render_blocks(&page.body, vars, "\n\n");
+        out.push('\n');
+
+        Ok(Output::Text(out))
+    }
+}
+
+fn render_blocks(tags: &[Tag], vars: &[String], separator: &str) -> String {
+    let mut blocks: Vec<String> = Vec::new();
+    let mut inline = String::new();
+
+    for tag in tags {
+        if is_block_among(tag, tags) {
+            if !inline.trim().is_empty() {
+                blocks.push(inline.trim().to_owned());
+            }
+            inline.clear();
+
+            let block = render_block(tag, vars);
+
+            if !block.is_empty() {
+                blocks.push(block);
+            }
+        } else {
+            inline.push_str(&render_inline(tag, vars));
+        }
+    }
+
+    if !inline.trim().is_empty() {
+        blocks.push(inline.trim().to_owned());
+    }
+
+    blocks.join(separator)
+}
+
+fn render_block(tag: &Tag, vars: &[String]) -> String {
+    match tag {
+        Tag::Element { body } | Tag::Paragraph { body } => render_body_blocks(body, vars),
+
+        Tag::Heading { body, heading } => {
+            let level = heading.clone() as usize;
+            format!("{} {}", "#".repeat(level), escape(body))
+        }
+
+        Tag::Table { body } => render_table(body, vars),
+
+        Tag::List { body, style } => body
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let marker = match style {
+                    ListStyle::Decimal => format!("{}. ", i + 1),
+                    ListStyle::Disc | ListStyle::None => "- ".to_owned(),
+                };
+
+                let item = match item {
+                    Tag::Element { body } => render_body_blocks_with(body, vars, "\n"),
+                    item if is_block(item) => render_block(item, vars),
+                    item => render_inline(item, vars),
+                };
+
+                format!("{marker}{}", indent(&item, marker.len()))
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+
+        Tag::BlockQuote { body } => render_body_blocks(body, vars)
+            .lines()
+            .map(|line| match line.is_empty() {
+                true => ">".to_owned(),
+                false => format!("> {line}"),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+
+        Tag::Code { body, language } => {
+            let fence = fence(body);
+            format!(
+                "{fence}{}\n{body}\n{fence}",
+                language.as_deref().unwrap_or_default()
+            )
+        }
+
+        Tag::Mono { body } => {
+            let fence = fence(body);
+            format!("{fence}\n{body}\n{fence}")
+        }
+
+        Tag::FootNote { body, footnote } => format!("[^{}]: {}", footnote + 1, escape(body)),
+
+        Tag::Block { body } | Tag::Flex { body, .. } | Tag::Grid { body, .. } => {
+            render_blocks(body, vars, "\n\n")
+        }
+
+        Tag::Carousel { body } => render_blocks(body, vars, "\n\n"),
+
+        Tag::Disclosure { body, title } => format!(
+            "<details>\n<summary>{}</summary>\n\n{}\n\n</details>",
+            title.as_deref().map(escape).unwrap_or_default(),
+            render_body_blocks(body, vars)
+        ),
+
+        Tag::HorizontalBreak => "---".to_owned(),
+
+        tag => render_inline(tag, vars),
+    }
+}
+
+fn render_body_blocks(body: &Body, vars: &[String]) -> String {
+    render_body_blocks_with(body, vars, "\n\n")
+}
+
+fn render_body_blocks_with(body: &Body, vars: &[String], separator: &str) -> String {
+    match body {
+        Body::Text(text) => escape(text),
+        Body::Tags(tags) => render_blocks(tags, vars, separator),
+    }
+}
+
+/// Leading primary row becomes header, otherwise header is left empty,
+/// as markdown tables can not have header rows elsewhere
+fn render_table(rows: &[TableRows], vars: &[String]) -> String {
+    let header = matches!(rows.first(), Some(TableRows::Primary(_)));
+
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| match row {
+            TableRows::Primary(cells) | TableRows::Default(cells) => cells
+                .iter()
+                .map(|cell| render_inline(cell, vars).replace('\n', " "))
+                .collect(),
+        })
+        .collect();
+
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+
+    if columns == 0 {
+        return String::new();
+    }
+
+    let row_line = |cells: &[String]| {
+        let cells: Vec<&str> = (0..columns)
+            .map(|i| cells.get(i).map(String::as_str).unwrap_or_default())
+            .collect();
+
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let (head, body) = match header {
+        true => (row_line(&rows[0]), &rows[1..]),
+        false => (row_line(&[]), &rows[..]),
+    };
+
+    let mut lines = vec![head, format!("|{}", " --- |".repeat(columns))];
+    lines.extend(body.iter().map(|row| row_line(row)));
+
+    lines.join("\n")
+}
+
+fn render_inline(tag: &Tag, vars: &[String]) -> String {
+    match tag {
+        Tag::Element { body } | Tag::Paragraph { body } | Tag::BlockQuote { body } => {
+            render_inline_body(body, vars)
+        }
+
+        Tag::Heading { body, .. } => escape(body),
+
+        Tag::Link { body, dref }
+        | Tag::NavLink { body, dref }
+        | Tag::Button { body, dref }
+        | Tag::NavButton { body, dref } => match body {
+            Some(body) => format!("[{}]({})", render_inline_body(body, vars), link_url(dref)),
+            None if is_autolink(dref) => format!("<{dref}>"),
+            None => format!("[{}]({})", escape(dref), link_url(dref)),
+        },
+
+        Tag::Image { src, alt } => format!(
+            "![{}]({})",
+            escape(alt.as_deref().unwrap_or_default()),
+            link_url(src)
+        ),
+
+        Tag::Bold { body } => format!("**{}**", escape(body)),
+        Tag::Italic { body } => format!("*{}*", escape(body)),
+        Tag::Strikethrough { body } => format!("~~{}~~", escape(body)),
+        Tag::Superscript { body } => format!("<sup>{}</sup>", escape(body)),
+        Tag::Subscript { body } => format!("<sub>{}</sub>", escape(body)),
+
+        Tag::Mono { body } | Tag::InlineCode { body, .. } => {
+            let ticks = "`".repeat(longest_run(body, '`') + 1);
+            let pad = match body.starts_with('`') || body.ends_with('`') {
+                true => " ",
+                false => "",
+            };
+
+            format!("{ticks}{pad}{body}{pad}{ticks}")
+        }
+
+        Tag::Code { body, .. } => escape(body),
+
+        Tag::FootLink { footnote } => format!("[^{}]", footnote + 1),
+        Tag::FootNote { body, .. } => escape(body),
+
+        Tag::Anchor { id } => format!("<a id=\"{}\"></a>", escape(id)),
+
+        Tag::Table { body } => body
+            .iter()
+            .map(|row| match row {
+                TableRows::Primary(cells) | TableRows::Default(cells) => cells
+                    .iter()
+                    .map(|cell| render_inline(cell, vars))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+
+        Tag::List { body, .. }
+        | Tag::Block { body }
+        | Tag::Flex { body, .. }
+        | Tag::Grid { body, .. }
+        | Tag::Carousel { body } => body
+            .iter()
+            .map(|tag| render_inline(tag, vars))
+            .collect::<Vec<_>>()
+            .join(" "),
+
+        Tag::Disclosure { body, .. } => render_inline_body(body, vars),
+
+        Tag::Variable { idx } => usize::try_from(*idx)
+            .ok()
+            .and_then(|idx| vars.get(idx))
+            .map(|var| escape(var))
+            .unwrap_or_default(),
+
+        Tag::HorizontalBreak => " ".to_owned(),
+    }
+}
+
+fn render_inline_body(body: &Body, vars: &[String]) -> String {
+    match body {
+        Body::Text(text) => escape(text),
+        Body::Tags(tags) => tags.iter().map(|tag| render_inline(tag, vars)).collect(),
+    }
+}
+
+fn indent(s: &str, width: usize) -> String {
+    let pad = " ".repeat(width);
+
+    s.lines()
+        .enumerate()
+        .map(|(i, line)| match i == 0 || line.is_empty() {
+            true => line.to_owned(),
+            false => format!("{pad}{line}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn fence(body: &str) -> String {
+    "`".repeat(longest_run(body, '`').max(2) + 1)
+}
+
+fn longest_run(s: &str, c: char) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+
+    for ch in s.chars() {
+        if ch == c {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+
+    longest
+}
+
+/// Autolinks are only recognised for absolute urls, e.g. `<https://example.com>`
+fn is_autolink(url: &str) -> bool {
+    let Some((scheme, rest)) = url.split_once(':') else {
+        return false;
+    };
+
+    (2..=32).contains(&scheme.len())
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+        && !rest.contains(|c: char| c.is_whitespace() || c.is_control() || c == '<' || c == '>')
+}
+
+fn link_url(url: &str) -> String {
+    match url.contains(|c: char| c.is_whitespace() || c == '(' || c == ')') {
+        true => format!("<{}>", url.replace('<', "%3C").replace('>', "%3E")),
+        false => url.to_owned(),
+    }
+}
+
+/// Escapes markdown syntax in literal text
+fn escape(s: &str) -> String {
+    s.split('\n')
+        .map(|line| {
+            let mut out = String::with_capacity(line.len());
+
+            for c in line.chars() {
+                if matches!(
+                    c,
+                    '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '~' | '!'
+                ) {
+                    out.push('\\');
+                }
+                out.push(c);
+            }
+
+            let trimmed = out.trim_start();
+            let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+
+            if trimmed.starts_with(['#', '+', '-', '=']) {
+                let at = out.len() - trimmed.len();
+                out.insert(at, '\\');
+            } else if digits > 0 && trimmed[digits..].starts_with(['.', ')']) {
+                let at = out.len() - trimmed.len() + digits;
+                out.insert(at, '\\');
+            }
+
+            out
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
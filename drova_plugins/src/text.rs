@@ -1,22 +1,25 @@
-use dalet::types::{Page, Tag};
-use drova_sdk::requester::{Error, InputHandler};
+use dalet::types::{Body, HeadingLevel, ListStyle, Page, TableRows, Tag};
+use drova_sdk::requester::{Error, InputHandler, Metadata, Output, OutputHandler};
+use unicode_width::UnicodeWidthStr;
+
+use crate::utils::{ascii_table, has_block, is_block_among};
 
 pub struct TextInput;
 
 impl InputHandler for TextInput {
-    fn process_text(&self, data: String, _: Option<&url::Url>) -> Result<Page, Error> {
+    fn process_text(&self, data: String, _: &Metadata) -> Result<Page, Error> {
         let title = truncate(&data, 20);
         let description = truncate(&data, 100);
 
         Ok(Page {
             title: Some(title.into()),
             description: Some(description.into()),
-            body: vec![Tag::Mono { body: data.into() }],
+            body: vec![Tag::Mono { body: data }],
             variables: None,
         })
     }
 
-    fn process_bytes(&self, _: Vec<u8>, _: Option<&url::Url>) -> Result<Page, Error> {
+    fn process_bytes(&self, _: Vec<u8>, _: &Metadata) -> Result<Page, Error> {
         Err(Error::UnsupportedInput)
     }
 }
@@ -27,3 +30,488 @@ pub fn truncate(s: &str, max_chars: usize) -> &str {
         None => s,
     }
 }
+
+/// Renders page to wrapped plain text, like `lynx -dump`
+pub struct TextOutput {
+    /// Column width text is wrapped at
+    pub width: usize,
+    /// Style text with ANSI escape codes for terminals
+    pub ansi: bool,
+}
+
+impl Default for TextOutput {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            ansi: false,
+        }
+    }
+}
+
+impl OutputHandler for TextOutput {
+    fn render(&self, page: &Page) -> Result<Output, Error> {
+        let mut writer = TextWriter {
+            output: self,
+            vars: page.variables.as_deref().unwrap_or_default(),
+            lines: Vec::new(),
+            links: Vec::new(),
+            footnotes: Vec::new(),
+        };
+
+        for tag in &page.body {
+            writer.block(tag, 0);
+        }
+
+        if !writer.footnotes.is_empty() {
+            let footnotes = std::mem::take(&mut writer.footnotes);
+
+            writer.heading("Footnotes", &HeadingLevel::Two);
+            writer.separate();
+
+            for (footnote, spans) in footnotes {
+                let marker = format!("[^{}] ", footnote + 1);
+                writer.wrap(spans, &marker, marker.width());
+            }
+        }
+
+        if !writer.links.is_empty() {
+            let links = std::mem::take(&mut writer.links);
+
+            writer.heading("References", &HeadingLevel::Two);
+            writer.separate();
+
+            for (i, link) in links.into_iter().enumerate() {
+                writer.lines.push(format!("{:>4}. {link}", i + 1));
+            }
+        }
+
+        let mut out = writer.lines.join("\n");
+        out.push('\n');
+
+        Ok(Output::Text(out))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Style {
+    Plain,
+    Bold,
+    Italic,
+    Strikethrough,
+    Link,
+    Dim,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Style::Plain => "",
+            Style::Bold => "\x1b[1m",
+            Style::Italic => "\x1b[3m",
+            Style::Strikethrough => "\x1b[9m",
+            Style::Link => "\x1b[4m",
+            Style::Dim => "\x1b[2m",
+        }
+    }
+}
+
+type Spans = Vec<(Style, String)>;
+
+struct TextWriter<'a> {
+    output: &'a TextOutput,
+    vars: &'a [String],
+    lines: Vec<String>,
+    links: Vec<String>,
+    footnotes: Vec<(u64, Spans)>,
+}
+
+impl TextWriter<'_> {
+    fn block(&mut self, tag: &Tag, indent: usize) {
+        let pad = " ".repeat(indent);
+
+        match tag {
+            Tag::Element { body } | Tag::Paragraph { body } => self.body(body, indent),
+
+            Tag::Heading { body, heading } => self.heading(body, heading),
+
+            Tag::Table { body } if body.is_empty() => {}
+
+            Tag::Table { body } => {
+                let rows = body
+                    .iter()
+                    .map(|row| match row {
+                        TableRows::Primary(cells) => (true, self.cells(cells)),
+                        TableRows::Default(cells) => (false, self.cells(cells)),
+                    })
+                    .collect();
+
+                self.separate();
+                self.lines
+                    .extend(ascii_table(rows).into_iter().map(|l| format!("{pad}{l}")));
+            }
+
+            Tag::List { body, style } => {
+                self.separate();
+                self.list(body, style, indent);
+            }
+
+            Tag::BlockQuote { body } => self.body(body, indent + 4),
+
+            Tag::Code { body, .. } => {
+                self.separate();
+                self.lines.extend(
+                    body.lines()
+                        .map(|l| format!("{pad}    {l}").trim_end().into()),
+                );
+            }
+
+            Tag::Mono { body } => {
+                self.separate();
+                self.lines
+                    .extend(body.lines().map(|l| format!("{pad}{l}").trim_end().into()));
+            }
+
+            Tag::FootNote { body, footnote } => self
+                .footnotes
+                .push((*footnote, vec![(Style::Plain, body.clone())])),
+
+            Tag::Block { body }
+            | Tag::Flex { body, .. }
+            | Tag::Grid { body, .. }
+            | Tag::Carousel { body } => {
+                for tag in body {
+                    self.block(tag, indent);
+                }
+            }
+
+            Tag::Disclosure { body, title } => {
+                if let Some(title) = title {
+                    self.separate();
+                    self.wrap(vec![(Style::Bold, title.clone())], &pad, indent);
+                }
+
+                self.body(body, indent);
+            }
+
+            Tag::HorizontalBreak => {
+                self.separate();
+                self.lines.push(format!(
+                    "{pad}{}",
+                    "-".repeat(self.output.width.saturating_sub(indent))
+                ));
+            }
+
+            tag => {
+                let mut spans = Spans::new();
+                self.inline(tag, Style::Plain, &mut spans);
+
+                self.separate();
+                self.wrap(spans, &pad, indent);
+            }
+        }
+    }
+
+    fn body(&mut self, body: &Body, indent: usize) {
+        match body {
+            Body::Tags(tags) if body_has_blocks(body) => {
+                for tag in tags {
+                    self.block(tag, indent);
+                }
+            }
+            body => {
+                let mut spans = Spans::new();
+                self.inline_body(body, Style::Plain, &mut spans);
+
+                self.separate();
+                self.wrap(spans, &" ".repeat(indent), indent);
+            }
+        }
+    }
+
+    fn heading(&mut self, text: &str, heading: &HeadingLevel) {
+        self.separate();
+
+        let start = self.lines.len();
+        self.wrap(vec![(Style::Bold, text.to_owned())], "", 0);
+
+        if self.output.ansi {
+            return;
+        }
+
+        let underline = match heading {
+            HeadingLevel::One => '=',
+            HeadingLevel::Two => '-',
+            _ => return,
+        };
+
+        let width = self.lines[start..]
+            .iter()
+            .map(|line| line.width())
+            .max()
+            .unwrap_or(0);
+
+        self.lines.push(underline.to_string().repeat(width));
+    }
+
+    fn list(&mut self, body: &[Tag], style: &ListStyle, indent: usize) {
+        for (i, item) in body.iter().enumerate() {
+            let marker = match style {
+                ListStyle::Disc => "* ".to_owned(),
+                ListStyle::Decimal => format!("{}. ", i + 1),
+                ListStyle::None => "  ".to_owned(),
+            };
+            let inner = indent + marker.width();
+            let mut prefix = Some(format!("{}{marker}", " ".repeat(indent)));
+
+            let children = match item {
+                Tag::Element {
+                    body: Body::Tags(tags),
+                } => tags.as_slice(),
+                item => std::slice::from_ref(item),
+            };
+
+            let mut spans = Spans::new();
+
+            for child in children {
+                match child {
+                    Tag::Paragraph { body } if !body_has_blocks(body) => {
+                        self.flush_item(&mut spans, &mut prefix, inner);
+                        self.inline_body(body, Style::Plain, &mut spans);
+                    }
+                    Tag::List { body, style } => {
+                        self.flush_item(&mut spans, &mut prefix, inner);
+                        self.list(body, style, inner);
+                    }
+                    child if is_block_among(child, children) => {
+                        self.flush_item(&mut spans, &mut prefix, inner);
+                        self.block(child, inner);
+                    }
+                    child => self.inline(child, Style::Plain, &mut spans),
+                }
+            }
+
+            self.flush_item(&mut spans, &mut prefix, inner);
+        }
+    }
+
+    /// Writes collected text of list item, first one gets item marker
+    fn flush_item(&mut self, spans: &mut Spans, prefix: &mut Option<String>, indent: usize) {
+        if spans.iter().all(|(_, text)| text.trim().is_empty()) {
+            return;
+        }
+
+        let prefix = prefix.take().unwrap_or_else(|| " ".repeat(indent));
+        self.wrap(std::mem::take(spans), &prefix, indent);
+    }
+
+    fn cells(&mut self, cells: &[Tag]) -> Vec<String> {
+        cells
+            .iter()
+            .map(|cell| {
+                let mut spans = Spans::new();
+                self.inline(cell, Style::Plain, &mut spans);
+
+                let text: String = spans.into_iter().map(|(_, text)| text).collect();
+
+                text.split_whitespace().collect::<Vec<_>>().join(" ")
+            })
+            .collect()
+    }
+
+    fn inline(&mut self, tag: &Tag, style: Style, spans: &mut Spans) {
+        match tag {
+            Tag::Element { body } | Tag::Paragraph { body } | Tag::BlockQuote { body } => {
+                self.inline_body(body, style, spans)
+            }
+
+            Tag::Link { body, dref }
+            | Tag::NavLink { body, dref }
+            | Tag::Button { body, dref }
+            | Tag::NavButton { body, dref } => {
+                match body {
+                    Some(body) => self.inline_body(body, Style::Link, spans),
+                    None => spans.push((Style::Link, dref.clone())),
+                }
+
+                self.reference(dref, spans);
+            }
+
+            Tag::Image { src, alt } => {
+                match alt {
+                    Some(alt) if !alt.is_empty() => spans.push((style, format!("[image: {alt}]"))),
+                    _ => spans.push((style, "[image]".into())),
+                }
+
+                self.reference(src, spans);
+            }
+
+            Tag::Heading { body, .. } | Tag::Bold { body } => {
+                spans.push((Style::Bold, body.clone()))
+            }
+            Tag::Italic { body } => spans.push((Style::Italic, body.clone())),
+            Tag::Strikethrough { body } => spans.push((Style::Strikethrough, body.clone())),
+
+            Tag::Superscript { body }
+            | Tag::Subscript { body }
+            | Tag::Mono { body }
+            | Tag::Code { body, .. }
+            | Tag::InlineCode { body, .. } => spans.push((style, body.clone())),
+
+            Tag::FootLink { footnote } => spans.push((Style::Dim, format!("[^{}]", footnote + 1))),
+
+            Tag::FootNote { body, footnote } => self
+                .footnotes
+                .push((*footnote, vec![(Style::Plain, body.clone())])),
+
+            Tag::Table { body } => {
+                for row in body {
+                    match row {
+                        TableRows::Primary(cells) | TableRows::Default(cells) => {
+                            for cell in cells {
+                                self.inline(cell, style, spans);
+                                spans.push((style, " ".into()));
+                            }
+                        }
+                    }
+                }
+            }
+
+            Tag::List { body, .. }
+            | Tag::Block { body }
+            | Tag::Flex { body, .. }
+            | Tag::Grid { body, .. }
+            | Tag::Carousel { body } => {
+                for tag in body {
+                    self.inline(tag, style, spans);
+                    spans.push((style, " ".into()));
+                }
+            }
+
+            Tag::Disclosure { body, title } => {
+                if let Some(title) = title {
+                    spans.push((Style::Bold, format!("{title} ")));
+                }
+
+                self.inline_body(body, style, spans);
+            }
+
+            Tag::Variable { idx } => {
+                if let Some(var) = usize::try_from(*idx)
+                    .ok()
+                    .and_then(|idx| self.vars.get(idx))
+                {
+                    spans.push((style, var.clone()));
+                }
+            }
+
+            Tag::Anchor { .. } => {}
+            Tag::HorizontalBreak => spans.push((style, " ".into())),
+        }
+    }
+
+    fn inline_body(&mut self, body: &Body, style: Style, spans: &mut Spans) {
+        match body {
+            Body::Text(text) => spans.push((style, text.clone())),
+            Body::Tags(tags) => {
+                for tag in tags {
+                    self.inline(tag, style, spans);
+                }
+            }
+        }
+    }
+
+    fn reference(&mut self, url: &str, spans: &mut Spans) {
+        self.links.push(url.to_owned());
+        spans.push((Style::Dim, format!("[{}]", self.links.len())));
+    }
+
+    /// Wraps text at output width, first line starts with prefix, others with indent
+    fn wrap(&mut self, spans: Spans, prefix: &str, indent: usize) {
+        let mut line = prefix.to_owned();
+        let mut line_width = prefix.width();
+        let mut empty = true;
+
+        for word in words(&spans) {
+            let Some(word) = word else {
+                self.lines.push(line.trim_end().to_owned());
+                line = " ".repeat(indent);
+                line_width = indent;
+                empty = true;
+                continue;
+            };
+
+            let width: usize = word.iter().map(|(_, text)| text.width()).sum();
+
+            if !empty && line_width + 1 + width > self.output.width {
+                self.lines.push(line.trim_end().to_owned());
+                line = " ".repeat(indent);
+                line_width = indent;
+                empty = true;
+            }
+
+            if !empty {
+                line.push(' ');
+                line_width += 1;
+            }
+
+            for (style, text) in word {
+                match self.output.ansi && style != Style::Plain {
+                    true => line.push_str(&format!("{}{text}\x1b[0m", style.code())),
+                    false => line.push_str(&text),
+                }
+            }
+
+            line_width += width;
+            empty = false;
+        }
+
+        if !empty {
+            self.lines.push(line.trim_end().to_owned());
+        }
+    }
+
+    /// Separates blocks with empty line
+    fn separate(&mut self) {
+        if self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+}
+
+/// Splits styled text into words, `None` is forced line break
+fn words(spans: &Spans) -> Vec<Option<Spans>> {
+    let mut words = Vec::new();
+    let mut word = Spans::new();
+
+    for (style, text) in spans {
+        for c in text.chars() {
+            if c.is_whitespace() {
+                if !word.is_empty() {
+                    words.push(Some(std::mem::take(&mut word)));
+                }
+                if c == '\n' {
+                    words.push(None);
+                }
+                continue;
+            }
+
+            match word.last_mut() {
+                Some((last, text)) if last == style => text.push(c),
+                _ => word.push((*style, c.to_string())),
+            }
+        }
+    }
+
+    if !word.is_empty() {
+        words.push(Some(word));
+    }
+
+    words
+}
+
+fn body_has_blocks(body: &Body) -> bool {
+    match body {
+        Body::Tags(tags) => has_block(tags),
+        Body::Text(_) => false,
+    }
+}
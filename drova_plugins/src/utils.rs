@@ -1,5 +1,282 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use dalet::types::{Body, Tag};
+use drova_sdk::requester::{BodyStream, Error};
+use encoding_rs::{Decoder, Encoding, UTF_8};
 use mime::Mime;
+use tokio::io::{AsyncRead, ReadBuf};
+use unicode_width::UnicodeWidthStr;
+use url::Url;
 
-pub fn mime_to_str<'a>(mime: Mime) -> String {
+pub fn mime_to_str(mime: &Mime) -> String {
     format!("{}/{}", mime.type_().as_str(), mime.subtype().as_str())
 }
+
+/// Parameters of mime type, e.g. charset or lang
+pub fn mime_params(mime: &Mime) -> Vec<(String, String)> {
+    mime.params()
+        .map(|(name, value)| (name.as_str().to_owned(), value.as_str().to_owned()))
+        .collect()
+}
+
+/// Resolves link against base url, links which can not be resolved are kept as is
+pub fn resolve_link(base: Option<&Url>, href: &str) -> String {
+    match base.and_then(|base| base.join(href).ok()) {
+        Some(url) => url.into(),
+        None => href.to_owned(),
+    }
+}
+
+/// Whether data of mime type should be decoded to text
+pub fn is_text(mime: &Mime) -> bool {
+    mime.type_() == mime::TEXT || mime.suffix() == Some(mime::XML)
+}
+
+/// Decodes text with encoding from BOM, `charset` parameter or html meta tag,
+/// falling back to UTF-8. Malformed sequences are replaced
+pub fn decode_text(bytes: &[u8], mime: &Mime) -> Result<String, Error> {
+    // BOM takes precedence over declared encoding
+    let (text, _, _) = encoding(bytes, mime)?.decode(bytes);
+
+    Ok(text.into_owned())
+}
+
+/// Same as [`decode_text`], but body is decoded to UTF-8 while it is read
+pub fn decode_stream(body: BodyStream, mime: &Mime) -> BodyStream {
+    BodyStream::new(DecodeReader {
+        inner: body,
+        mime: mime.clone(),
+        head: Vec::new(),
+        decoder: None,
+        out: Vec::new(),
+        pos: 0,
+        ended: false,
+    })
+}
+
+/// Encoding of text from its beginning, BOM is checked by decoder
+fn encoding(head: &[u8], mime: &Mime) -> Result<&'static Encoding, Error> {
+    let charset = mime.get_param(mime::CHARSET);
+
+    let declared = charset.and_then(|label| Encoding::for_label(label.as_str().as_bytes()));
+    let sniffed = match mime.subtype() == mime::HTML {
+        true => meta_charset(head),
+        false => None,
+    };
+
+    match (declared, sniffed) {
+        (Some(encoding), _) | (None, Some(encoding)) => Ok(encoding),
+        // Unknown charset, guessing could give garbage
+        (None, None) if charset.is_some() && Encoding::for_bom(head).is_none() => {
+            Err(Error::InvalidEncoding)
+        }
+        (None, None) => Ok(UTF_8),
+    }
+}
+
+/// Html meta charset is looked for in this many first bytes
+const HEAD_SIZE: usize = 1024;
+
+struct DecodeReader {
+    inner: BodyStream,
+    mime: Mime,
+    /// Beginning of body, kept until encoding is known
+    head: Vec<u8>,
+    decoder: Option<Decoder>,
+    /// Decoded text not read yet
+    out: Vec<u8>,
+    pos: usize,
+    ended: bool,
+}
+
+impl AsyncRead for DecodeReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+
+        loop {
+            if this.pos < this.out.len() {
+                let len = buf.remaining().min(this.out.len() - this.pos);
+                buf.put_slice(&this.out[this.pos..this.pos + len]);
+                this.pos += len;
+
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.ended {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut chunk = [0u8; 8192];
+            let mut chunk = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+
+            let chunk = chunk.filled();
+            let last = chunk.is_empty();
+
+            let input = match &this.decoder {
+                Some(_) => chunk.to_vec(),
+                None => {
+                    this.head.extend_from_slice(chunk);
+
+                    if this.head.len() < HEAD_SIZE && !last {
+                        continue;
+                    }
+
+                    let encoding = encoding(&this.head, &this.mime).map_err(io::Error::other)?;
+                    this.decoder = Some(encoding.new_decoder());
+
+                    std::mem::take(&mut this.head)
+                }
+            };
+
+            // Decoder was set above
+            let decoder = this.decoder.as_mut().unwrap();
+
+            let mut text = String::with_capacity(
+                decoder
+                    .max_utf8_buffer_length(input.len())
+                    .unwrap_or(input.len() * 3 + 16),
+            );
+            let _ = decoder.decode_to_string(&input, &mut text, last);
+
+            this.out = text.into_bytes();
+            this.pos = 0;
+            this.ended = last;
+        }
+    }
+}
+
+/// Charset of `<meta charset>` or `<meta http-equiv="Content-Type">` in beginning of html
+fn meta_charset(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]).to_ascii_lowercase();
+
+    for tag in head.split("<meta").skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+
+        let Some((_, value)) = tag.split_once("charset") else {
+            continue;
+        };
+
+        let Some(value) = value.trim_start().strip_prefix('=') else {
+            continue;
+        };
+
+        let label = value
+            .trim_start()
+            .trim_start_matches(['"', '\''])
+            .split(['"', '\'', ';', ' ', '/'])
+            .next()
+            .unwrap_or_default();
+
+        if let Some(encoding) = Encoding::for_label(label.as_bytes()) {
+            // Document is already read as ascii-compatible, so it can not be UTF-16
+            return Some(encoding.output_encoding());
+        }
+    }
+
+    None
+}
+
+/// Formats table rows as aligned ascii lines, primary rows are underlined
+pub fn ascii_table(rows: Vec<(bool, Vec<String>)>) -> Vec<String> {
+    let columns = rows.iter().map(|(_, cells)| cells.len()).max().unwrap_or(0);
+    let mut widths = vec![0; columns];
+
+    for (_, cells) in &rows {
+        for (i, cell) in cells.iter().enumerate() {
+            widths[i] = widths[i].max(cell.width());
+        }
+    }
+
+    let mut lines = Vec::new();
+
+    for (primary, cells) in rows {
+        let line = widths
+            .iter()
+            .enumerate()
+            .map(|(i, width)| {
+                let cell = cells.get(i).map(String::as_str).unwrap_or_default();
+                let pad = width - cell.width();
+
+                format!("{cell}{}", " ".repeat(pad))
+            })
+            .collect::<Vec<_>>()
+            .join(" | ");
+
+        lines.push(line.trim_end().to_owned());
+
+        if primary {
+            lines.push(
+                widths
+                    .iter()
+                    .map(|width| "-".repeat(*width))
+                    .collect::<Vec<_>>()
+                    .join("-+-"),
+            );
+        }
+    }
+
+    lines
+}
+
+/// Whether tag is rendered as separate block, elements are blocks if they contain ones
+pub fn is_block(tag: &Tag) -> bool {
+    match tag {
+        Tag::Element {
+            body: Body::Tags(tags),
+        } => has_block(tags),
+        Tag::Paragraph { .. }
+        | Tag::Heading { .. }
+        | Tag::Table { .. }
+        | Tag::List { .. }
+        | Tag::BlockQuote { .. }
+        | Tag::Code { .. }
+        | Tag::FootNote { .. }
+        | Tag::Block { .. }
+        | Tag::Flex { .. }
+        | Tag::Grid { .. }
+        | Tag::Carousel { .. }
+        | Tag::Disclosure { .. }
+        | Tag::HorizontalBreak => true,
+        _ => false,
+    }
+}
+
+/// Same as [`is_block`], but Mono is block only when it stands alone among blocks,
+/// e.g. `<pre>` but not `<kbd>`. Blank text between blocks is not counted
+pub fn is_block_among(tag: &Tag, siblings: &[Tag]) -> bool {
+    let is_mono = |tag: &&Tag| matches!(tag, Tag::Mono { .. });
+
+    match tag {
+        Tag::Mono { .. } => {
+            siblings.iter().filter(is_mono).count() == 1
+                && siblings
+                    .iter()
+                    .all(|tag| is_block(tag) || is_blank(tag) || is_mono(&tag))
+        }
+        tag => is_block(tag),
+    }
+}
+
+/// Whether some of tags is rendered as separate block
+pub fn has_block(tags: &[Tag]) -> bool {
+    tags.iter().any(|tag| is_block_among(tag, tags))
+}
+
+/// Text element of only whitespace
+pub fn is_blank(tag: &Tag) -> bool {
+    match tag {
+        Tag::Element {
+            body: Body::Text(text),
+        } => text.trim().is_empty(),
+        _ => false,
+    }
+}
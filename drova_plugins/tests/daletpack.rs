@@ -0,0 +1,194 @@
+use dalet::types::{Align, Body, HeadingLevel, ListStyle, Page, TableRows, Tag};
+use drova_plugins::daletpack::{DaletpackInput, DaletpackOutput};
+use drova_sdk::requester::{Error, InputHandler, Metadata, Output, OutputHandler};
+
+fn roundtrip(page: Page) {
+    let bytes = match DaletpackOutput.render(&page).unwrap() {
+        Output::Bits(bytes) => bytes,
+        Output::Text(_) => panic!("daletpack output must be binary"),
+    };
+
+    assert_eq!(
+        DaletpackInput
+            .process_bytes(bytes, &Metadata::default())
+            .unwrap(),
+        page
+    );
+}
+
+fn page(body: Vec<Tag>) -> Page {
+    Page {
+        title: Some("Title".into()),
+        description: Some("Description".into()),
+        body,
+        variables: Some(vec!["first".into(), "second".into()]),
+    }
+}
+
+#[test]
+fn empty_page() {
+    roundtrip(Page {
+        title: None,
+        description: None,
+        body: vec![],
+        variables: None,
+    });
+}
+
+#[test]
+fn text_tags() {
+    roundtrip(page(vec![
+        Tag::Element {
+            body: "element".to_owned().into(),
+        },
+        Tag::Heading {
+            body: "heading".into(),
+            heading: HeadingLevel::Three,
+        },
+        Tag::Paragraph {
+            body: vec![
+                Tag::Bold {
+                    body: "bold".into(),
+                },
+                Tag::Italic {
+                    body: "italic".into(),
+                },
+                Tag::Strikethrough {
+                    body: "strikethrough".into(),
+                },
+                Tag::Superscript { body: "sup".into() },
+                Tag::Subscript { body: "sub".into() },
+                Tag::Mono {
+                    body: "mono".into(),
+                },
+            ]
+            .into(),
+        },
+        Tag::BlockQuote {
+            body: "quote".to_owned().into(),
+        },
+        Tag::Code {
+            body: "fn main() {}".into(),
+            language: Some("rust".into()),
+        },
+        Tag::InlineCode {
+            body: "x".into(),
+            language: None,
+        },
+        Tag::HorizontalBreak,
+    ]));
+}
+
+#[test]
+fn link_tags() {
+    roundtrip(page(vec![
+        Tag::Link {
+            body: Some("link".to_owned().into()),
+            dref: "https://example.com".into(),
+        },
+        Tag::NavLink {
+            body: None,
+            dref: "gemini://example.com".into(),
+        },
+        Tag::Button {
+            body: Some(vec![Tag::Bold { body: "b".into() }].into()),
+            dref: "https://example.com/button".into(),
+        },
+        Tag::NavButton {
+            body: None,
+            dref: "/relative".into(),
+        },
+        Tag::Image {
+            src: "image.png".into(),
+            alt: Some("alt".into()),
+        },
+        Tag::Anchor {
+            id: "anchor".into(),
+        },
+        Tag::FootLink { footnote: 0 },
+        Tag::FootNote {
+            body: "footnote".into(),
+            footnote: 0,
+        },
+        Tag::Variable { idx: 1 },
+    ]));
+}
+
+#[test]
+fn container_tags() {
+    roundtrip(page(vec![
+        Tag::Table {
+            body: vec![
+                TableRows::Primary(vec![
+                    Tag::Element {
+                        body: "a".to_owned().into(),
+                    },
+                    Tag::Element {
+                        body: "b".to_owned().into(),
+                    },
+                ]),
+                TableRows::Default(vec![
+                    Tag::Element {
+                        body: "1".to_owned().into(),
+                    },
+                    Tag::Element {
+                        body: "2".to_owned().into(),
+                    },
+                ]),
+            ],
+        },
+        Tag::List {
+            body: vec![
+                Tag::Element {
+                    body: "item".to_owned().into(),
+                },
+                Tag::List {
+                    body: vec![Tag::Element {
+                        body: "nested".to_owned().into(),
+                    }],
+                    style: ListStyle::None,
+                },
+            ],
+            style: ListStyle::Decimal,
+        },
+        Tag::Block {
+            body: vec![Tag::HorizontalBreak],
+        },
+        Tag::Flex {
+            body: vec![Tag::Mono { body: "m".into() }],
+            wrap: true,
+            align_x: Some(Align::Center),
+            align_y: None,
+        },
+        Tag::Grid {
+            body: vec![],
+            align_x: Some(Align::Start),
+            align_y: Some(Align::End),
+        },
+        Tag::Disclosure {
+            body: Body::Tags(vec![Tag::Paragraph {
+                body: "hidden".to_owned().into(),
+            }]),
+            title: Some("title".into()),
+        },
+        Tag::Carousel {
+            body: vec![Tag::Block { body: vec![] }, Tag::Block { body: vec![] }],
+        },
+    ]));
+}
+
+#[test]
+fn text_is_unsupported() {
+    assert!(matches!(
+        DaletpackInput.process_text("text".into(), &Metadata::default()),
+        Err(Error::UnsupportedInput)
+    ));
+}
+
+#[test]
+fn invalid_data() {
+    assert!(matches!(
+        DaletpackInput.process_bytes(vec![0, 1, 2, 3], &Metadata::default()),
+        Err(Error::ParserError(_))
+    ));
+}
@@ -0,0 +1,74 @@
+use dalet::types::{HeadingLevel, ListStyle, Tag};
+use drova_plugins::gemini::gemtext::GemtextInput;
+use drova_sdk::requester::{InputHandler, Metadata};
+
+fn parse(s: &str) -> Vec<Tag> {
+    GemtextInput
+        .process_text(s.into(), &Metadata::default())
+        .unwrap()
+        .body
+}
+
+#[test]
+fn line_types_at_start_of_line() {
+    assert_eq!(
+        parse("# Title\n* item\n> quote\n=> /path link\n"),
+        vec![
+            Tag::Heading {
+                body: "Title".into(),
+                heading: HeadingLevel::One,
+            },
+            Tag::List {
+                body: vec![Tag::Element {
+                    body: "item".into(),
+                }],
+                style: ListStyle::Disc,
+            },
+            Tag::BlockQuote {
+                body: "quote".into(),
+            },
+            Tag::Paragraph {
+                body: vec![Tag::NavLink {
+                    body: Some("link".into()),
+                    dref: "/path".into(),
+                }]
+                .into(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn indented_lines_are_text() {
+    assert_eq!(
+        parse(" # not heading\n  * not item\n\t=> not link\n ```\n"),
+        vec![
+            Tag::Paragraph {
+                body: "# not heading".into(),
+            },
+            Tag::Paragraph {
+                body: "* not item".into(),
+            },
+            Tag::Paragraph {
+                body: "=> not link".into(),
+            },
+            Tag::Paragraph { body: "```".into() },
+        ]
+    );
+}
+
+#[test]
+fn trailing_whitespace_is_ignored() {
+    assert_eq!(
+        parse("## Heading  \ntext \t\n"),
+        vec![
+            Tag::Heading {
+                body: "Heading".into(),
+                heading: HeadingLevel::Two,
+            },
+            Tag::Paragraph {
+                body: "text".into(),
+            },
+        ]
+    );
+}
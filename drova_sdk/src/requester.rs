@@ -1,14 +1,41 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::{Duration, SystemTime},
+};
+
 use async_trait::async_trait;
-use dalet::types::Page;
+use dalet::types::{Page, Tag};
 use glob_match::glob_match;
 use indexmap::IndexMap;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader, ReadBuf},
+    time::{sleep, sleep_until, timeout, timeout_at, Instant, Sleep},
+};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
 use url::Url;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Characters escaped in query answers, everything except unreserved ones (RFC 3986)
+const QUERY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Error {
-    GetInput,
-    GetSecureInput,
+    /// Server asks for input with prompt, answer with [`Requester::process_input`]
+    GetInput(String),
+    /// Same as [`Error::GetInput`], but input should not be echoed (e.g. password)
+    GetSecureInput(String),
     ClientCertRequired,
 
     NotAuthorized,
@@ -19,24 +46,39 @@ pub enum Error {
     InvalidUrl,
     UnsupportedProtocol,
     UnsupportedInput,
+    UnsupportedOutput,
 
     IoError(String),
     DnsFailed,
     TlsFailed,
     ExceededStatusSize,
+    /// Body is larger than [`FetchOptions::max_body_size`]
+    ExceededBodySize,
+    /// Headers or meta line are larger than [`FetchOptions::max_header_size`]
+    ExceededHeaderSize,
     InvalidEncoding,
     InvalidMimeType(String),
 
     InvalidCert,
+    /// Certificate of host differs from pinned one, which is not expired yet
+    CertChanged(String),
 
     ExceededMaxRedirects,
+    RedirectLoop,
+
+    /// Connecting, waiting for response or whole request took too long
+    Timeout,
+    /// Request was cancelled through [`FetchOptions::cancel`]
+    Cancelled,
 
     TemporalFailure,
     Failure,
 
-    ServerUnavailable,
+    /// Server is unavailable, with seconds to wait if server told them
+    ServerUnavailable(Option<u64>),
 
-    TooManyRequests,
+    /// Rate limit was hit, with seconds to wait if server told them
+    TooManyRequests(Option<u64>),
 
     Gone,
 
@@ -50,6 +92,127 @@ pub enum Error {
     ParserError(String),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Limits, timeouts and cancellation of request, given to protocol handler.
+///
+/// Defaults are set on [`RequesterBuilder`], per request ones are passed
+/// to `_with` methods of [`Requester`], e.g. [`Requester::process_with`]
+#[derive(Debug, Clone, Default)]
+pub struct FetchOptions {
+    /// Maximum size of body in bytes
+    pub max_body_size: Option<usize>,
+    /// Maximum size of headers or meta line in bytes
+    pub max_header_size: Option<usize>,
+
+    /// Timeout of connecting, including TLS handshake
+    pub connect_timeout: Option<Duration>,
+    /// Timeout of waiting for response after request was sent
+    pub first_byte_timeout: Option<Duration>,
+    /// Timeout of whole request including body, enforced by requester
+    pub timeout: Option<Duration>,
+
+    /// Cancels request, protocol handlers may stop early on it.
+    /// Not set by default, so cancelling one request never affects others
+    pub cancel: Option<CancellationToken>,
+}
+
+impl FetchOptions {
+    /// Runs connecting within [`FetchOptions::connect_timeout`]
+    pub async fn connect<T>(&self, fut: impl Future<Output = T>) -> Result<T, Error> {
+        limit_time(self.connect_timeout, fut).await
+    }
+
+    /// Runs waiting for response within [`FetchOptions::first_byte_timeout`]
+    pub async fn first_byte<T>(&self, fut: impl Future<Output = T>) -> Result<T, Error> {
+        limit_time(self.first_byte_timeout, fut).await
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+}
+
+async fn limit_time<T>(time: Option<Duration>, fut: impl Future<Output = T>) -> Result<T, Error> {
+    match time {
+        Some(time) => timeout(time, fut).await.map_err(|_| Error::Timeout),
+        None => Ok(fut.await),
+    }
+}
+
+/// Retrying of failed requests with exponential backoff, see [`RequesterBuilder::retry`]
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of retries of one request
+    pub max_retries: usize,
+    /// Delay before first retry, doubled for each next one
+    pub base_delay: Duration,
+    /// Maximum delay, errors asking to wait longer are not retried
+    pub max_delay: Duration,
+    /// Whether error should be retried
+    pub retryable: fn(&Error) -> bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            retryable: RetryPolicy::is_transient,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Errors which may go away by themselves
+    pub fn is_transient(e: &Error) -> bool {
+        matches!(
+            e,
+            Error::TemporalFailure
+                | Error::ServerUnavailable(_)
+                | Error::TooManyRequests(_)
+                | Error::Timeout
+        )
+    }
+
+    /// Delay before retry after error, `None` if it should not be retried
+    fn delay(&self, e: &Error, attempt: usize) -> Option<Duration> {
+        if attempt >= self.max_retries || !(self.retryable)(e) {
+            return None;
+        }
+
+        match e {
+            Error::TooManyRequests(Some(secs)) | Error::ServerUnavailable(Some(secs)) => {
+                let wait = Duration::from_secs(*secs);
+                (wait <= self.max_delay).then_some(wait)
+            }
+            _ => {
+                let backoff = self
+                    .base_delay
+                    .saturating_mul(1 << attempt.min(16))
+                    .min(self.max_delay);
+
+                Some(jitter(backoff))
+            }
+        }
+    }
+}
+
+/// Random delay between half and whole of backoff
+fn jitter(backoff: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    let half = backoff / 2;
+
+    half + half.mul_f64(random as f64 / u64::MAX as f64)
+}
+
 /// Response from protocol handler
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Response {
@@ -57,6 +220,39 @@ pub struct Response {
 
     /// Type of data for input handler. e.g. text/html
     pub ty: String,
+
+    pub meta: Metadata,
+}
+
+/// Metadata of response, passed to input handler
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+    /// Url of document, after following redirects
+    pub url: Option<Url>,
+    /// Parameters of type, e.g. charset, lang or variant
+    pub params: Vec<(String, String)>,
+    /// Headers or other protocol specific fields
+    pub headers: Vec<(String, String)>,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Metadata {
+    /// Value of type parameter, name is case-insensitive
+    pub fn param(&self, name: &str) -> Option<&str> {
+        find(&self.params, name)
+    }
+
+    /// Value of first header with name, name is case-insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find(&self.headers, name)
+    }
+}
+
+fn find<'a>(pairs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    pairs
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
 }
 
 /// Response data of protocol handler
@@ -64,123 +260,826 @@ pub struct Response {
 pub enum ResponseData {
     TextOutput(String),
     BitsOutput(Vec<u8>),
+    /// Body which is still arriving, see [`ProtocolHandler::fetch_stream`].
+    /// Text body is decoded to UTF-8 and buffered as [`ResponseData::TextOutput`]
+    #[serde(skip)]
+    Stream {
+        body: BodyStream,
+        text: bool,
+    },
+    /// Document was moved to another url, possibly with other scheme
+    Redirect {
+        url: Url,
+        permanent: bool,
+    },
+}
+
+/// Response body read as it arrives
+pub struct BodyStream(Pin<Box<dyn AsyncRead + Send>>);
+
+impl BodyStream {
+    pub fn new(reader: impl AsyncRead + Send + 'static) -> Self {
+        Self(Box::pin(reader))
+    }
+
+    /// Reading fails with [`Error::ExceededBodySize`] after `max` bytes
+    pub fn max_size(self, max: Option<usize>) -> Self {
+        match max {
+            Some(max) => Self::new(Limited {
+                inner: self,
+                left: Some(max),
+                deadline: None,
+                cancelled: None,
+            }),
+            None => self,
+        }
+    }
+
+    /// Reading fails with [`Error::Timeout`] after deadline
+    pub fn deadline(self, deadline: Option<Instant>) -> Self {
+        match deadline {
+            Some(deadline) => Self::new(Limited {
+                inner: self,
+                left: None,
+                deadline: Some(Box::pin(sleep_until(deadline))),
+                cancelled: None,
+            }),
+            None => self,
+        }
+    }
+
+    /// Reading fails with [`Error::Cancelled`] after token is cancelled
+    pub fn cancel_on(self, token: Option<CancellationToken>) -> Self {
+        match token {
+            Some(token) => Self::new(Limited {
+                inner: self,
+                left: None,
+                deadline: None,
+                cancelled: Some(Box::pin(token.cancelled_owned())),
+            }),
+            None => self,
+        }
+    }
+
+    /// Reads rest of body as UTF-8 text or as bytes
+    pub async fn buffer(mut self, text: bool) -> Result<ResponseData, Error> {
+        let mut bytes = Vec::new();
+        self.read_to_end(&mut bytes).await?;
+
+        match text {
+            true => String::from_utf8(bytes)
+                .map(ResponseData::TextOutput)
+                .map_err(|_| Error::InvalidEncoding),
+            false => Ok(ResponseData::BitsOutput(bytes)),
+        }
+    }
+}
+
+impl AsyncRead for BodyStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.0.as_mut().poll_read(cx, buf)
+    }
+}
+
+struct Limited {
+    inner: BodyStream,
+    left: Option<usize>,
+    deadline: Option<Pin<Box<Sleep>>>,
+    cancelled: Option<Pin<Box<WaitForCancellationFutureOwned>>>,
+}
+
+impl AsyncRead for Limited {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if let Some(deadline) = &mut self.deadline {
+            if deadline.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(io::Error::other(Error::Timeout)));
+            }
+        }
+
+        if let Some(cancelled) = &mut self.cancelled {
+            if cancelled.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(io::Error::other(Error::Cancelled)));
+            }
+        }
+
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        let read = buf.filled().len() - filled;
+
+        if let Some(left) = &mut self.left {
+            match left.checked_sub(read) {
+                Some(rest) => *left = rest,
+                None => {
+                    // Readers must not fill buffer when failing
+                    buf.set_filled(filled);
+                    return Poll::Ready(Err(io::Error::other(Error::ExceededBodySize)));
+                }
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BodyStream")
+    }
+}
+
+/// Page rendered by output handler
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Output {
+    Text(String),
+    Bits(Vec<u8>),
+}
+
+/// Dalet page with url of document it was extracted from
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Document {
+    /// Final url of document, after following redirects
+    pub url: Url,
+    pub page: Page,
+    /// Metadata of response, e.g. language of page
+    pub meta: Metadata,
 }
 
 #[async_trait]
 pub trait ProtocolHandler: Send + Sync {
-    async fn fetch(&self, url: &Url) -> Result<Response, Error>;
+    async fn fetch(&self, url: &Url, opts: &FetchOptions) -> Result<Response, Error>;
+
+    /// Same as [`ProtocolHandler::fetch`], but body may be returned as [`ResponseData::Stream`].
+    /// Streamed text should be decoded to UTF-8. By default body is buffered
+    async fn fetch_stream(&self, url: &Url, opts: &FetchOptions) -> Result<Response, Error> {
+        self.fetch(url, opts).await
+    }
+}
+
+/// Wraps every call of protocol handlers, e.g. for logging, auth, caching or rate limiting.
+/// Can change url or options, inspect response or return own one without calling `next`
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    async fn handle(
+        &self,
+        url: &Url,
+        opts: &FetchOptions,
+        next: Next<'_>,
+    ) -> Result<Response, Error>;
+}
+
+/// Rest of middleware chain, ending with protocol handler
+pub struct Next<'a> {
+    protocol: &'a dyn ProtocolHandler,
+    chain: &'a [Arc<dyn Middleware>],
+    stream: bool,
+}
+
+impl Next<'_> {
+    /// Whether body may be returned as [`ResponseData::Stream`]
+    pub fn stream(&self) -> bool {
+        self.stream
+    }
+
+    /// Calls next middleware or protocol handler.
+    /// Protocol handler is already chosen, so url should keep its scheme
+    pub async fn run(self, url: &Url, opts: &FetchOptions) -> Result<Response, Error> {
+        match self.chain.split_first() {
+            Some((middleware, chain)) => {
+                let next = Next {
+                    protocol: self.protocol,
+                    chain,
+                    stream: self.stream,
+                };
+
+                middleware.handle(url, opts, next).await
+            }
+            None => match self.stream {
+                true => self.protocol.fetch_stream(url, opts).await,
+                false => self.protocol.fetch(url, opts).await,
+            },
+        }
+    }
 }
 
 pub trait InputHandler: Send + Sync {
-    fn process_text(&self, s: String, url: Option<&Url>) -> Result<Page, Error>;
-    fn process_bytes(&self, b: Vec<u8>, url: Option<&Url>) -> Result<Page, Error>;
+    fn process_text(&self, s: String, meta: &Metadata) -> Result<Page, Error>;
+    fn process_bytes(&self, b: Vec<u8>, meta: &Metadata) -> Result<Page, Error>;
+}
+
+/// Input handler for line-oriented formats, which can be parsed while document arrives
+pub trait LineInput: Send + Sync {
+    fn parser(&self, meta: &Metadata) -> Box<dyn LineParser>;
+}
+
+/// State of parsing one document line by line
+pub trait LineParser: Send {
+    /// Parses next line without line ending, returns tags completed by now
+    fn line(&mut self, line: &str) -> Result<Vec<Tag>, Error>;
+    /// Ends document, returns tags which were not completed yet
+    fn finish(&mut self) -> Result<Vec<Tag>, Error>;
+    /// Title of page, if already known
+    fn title(&self) -> Option<&str>;
+}
+
+/// Dalet page parsed while document arrives, see [`Requester::process_stream`]
+pub struct DocumentStream {
+    /// Final url of document, after following redirects
+    pub url: Url,
+    pub meta: Metadata,
+    source: Source,
+}
+
+enum Source {
+    Lines {
+        body: BufReader<BodyStream>,
+        parser: Box<dyn LineParser>,
+        ended: bool,
+    },
+    Page {
+        page: Page,
+        taken: bool,
+    },
+}
+
+impl DocumentStream {
+    /// Next parsed tags, `None` after document ended
+    pub async fn next(&mut self) -> Option<Result<Vec<Tag>, Error>> {
+        let (body, parser, ended) = match &mut self.source {
+            Source::Lines {
+                body,
+                parser,
+                ended,
+            } => (body, parser, ended),
+            Source::Page { page, taken } => {
+                return match std::mem::replace(taken, true) {
+                    true => None,
+                    false => Some(Ok(std::mem::take(&mut page.body))),
+                };
+            }
+        };
+
+        let mut line = Vec::new();
+
+        while !*ended {
+            line.clear();
+
+            let tags = match body.read_until(b'\n', &mut line).await {
+                Ok(0) => {
+                    *ended = true;
+                    parser.finish()
+                }
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&line);
+                    parser.line(line.trim_end_matches(['\n', '\r']))
+                }
+                Err(e) => {
+                    *ended = true;
+                    Err(e.into())
+                }
+            };
+
+            match tags {
+                Ok(tags) if tags.is_empty() => continue,
+                res => return Some(res),
+            }
+        }
+
+        None
+    }
+
+    /// Title of page, if already known
+    pub fn title(&self) -> Option<&str> {
+        match &self.source {
+            Source::Lines { parser, .. } => parser.title(),
+            Source::Page { page, .. } => page.title.as_deref(),
+        }
+    }
+
+    /// Reads rest of document, collecting tags which were not taken yet
+    pub async fn page(mut self) -> Result<Page, Error> {
+        let mut body = Vec::new();
+
+        while let Some(tags) = self.next().await {
+            body.extend(tags?);
+        }
+
+        Ok(match self.source {
+            Source::Lines { parser, .. } => Page {
+                title: parser.title().map(String::from),
+                description: None,
+                body,
+                variables: None,
+            },
+            Source::Page { page, .. } => Page { body, ..page },
+        })
+    }
+}
+
+/// Post-processes dalet pages produced by input handlers,
+/// e.g. for proxying links, stripping images or generating table of contents.
+/// Transformer gets whole page, so streamed documents in its scope are buffered
+pub trait PageTransformer: Send + Sync {
+    fn transform(&self, page: Page, meta: &Metadata) -> Result<Page, Error>;
+}
+
+/// Pages which transformer is run on, see [`RequesterBuilder::scoped_transformer`].
+/// Patterns are globs, empty scope matches every page
+#[derive(Debug, Clone, Default)]
+pub struct TransformScope {
+    /// Pattern of url, e.g. `gemini://example.com/**`
+    pub url: Option<String>,
+    /// Pattern of input type, e.g. `text/*`
+    pub ty: Option<String>,
+}
+
+impl TransformScope {
+    pub fn url(pattern: &str) -> Self {
+        Self {
+            url: Some(pattern.into()),
+            ty: None,
+        }
+    }
+
+    pub fn ty(pattern: &str) -> Self {
+        Self {
+            url: None,
+            ty: Some(pattern.into()),
+        }
+    }
+
+    fn matches(&self, url: Option<&Url>, ty: &str) -> bool {
+        let url = match (&self.url, url) {
+            (None, _) => true,
+            (Some(pattern), Some(url)) => glob_match(pattern, url.as_str()),
+            (Some(_), None) => false,
+        };
+
+        url && self
+            .ty
+            .as_ref()
+            .is_none_or(|pattern| glob_match(pattern, ty))
+    }
+}
+
+/// Renders dalet page back to some format
+pub trait OutputHandler: Send + Sync {
+    fn render(&self, page: &Page) -> Result<Output, Error>;
 }
 
 /// Requester is system for extracting dalet from anything,
-/// through protocol and input handlers
-pub struct Requester<'a> {
-    protocols: IndexMap<String, &'a dyn ProtocolHandler>,
-    inputs: IndexMap<String, &'a dyn InputHandler>,
+/// through protocol and input handlers.
+///
+/// Input handlers and page transformers are run on tokio blocking pool by [`Requester::process`].
+///
+/// Handlers are shared, so cloning requester is cheap.
+#[derive(Clone)]
+pub struct Requester {
+    protocols: IndexMap<String, Arc<dyn ProtocolHandler>>,
+    inputs: IndexMap<String, Arc<dyn InputHandler>>,
+    line_inputs: IndexMap<String, Arc<dyn LineInput>>,
+    outputs: IndexMap<String, Arc<dyn OutputHandler>>,
+    max_redirects: usize,
+    options: FetchOptions,
+    retry: Option<RetryPolicy>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    transformers: Vec<(TransformScope, Arc<dyn PageTransformer>)>,
 }
 
-impl Default for Requester<'_> {
+impl Default for Requester {
     fn default() -> Self {
         Self {
             protocols: IndexMap::new(),
             inputs: IndexMap::new(),
+            line_inputs: IndexMap::new(),
+            outputs: IndexMap::new(),
+            max_redirects: 5,
+            options: FetchOptions::default(),
+            retry: None,
+            middlewares: Vec::new(),
+            transformers: Vec::new(),
         }
     }
 }
 
-impl<'a> Requester<'a> {
-    /// Process url and get dalet page
-    pub async fn process(&self, url: &str) -> Result<Page, Error> {
-        use ResponseData::*;
+impl Requester {
+    /// Default options of requests, base for per request ones
+    pub fn options(&self) -> &FetchOptions {
+        &self.options
+    }
 
-        let url = Url::parse(url)?;
+    /// Process url and get dalet page, following redirects
+    pub async fn process(&self, url: &str) -> Result<Document, Error> {
+        self.process_with(url, &self.options).await
+    }
 
-        let scheme = url.scheme();
+    /// Same as [`Requester::process`], but with own limits, timeouts and cancellation
+    pub async fn process_with(&self, url: &str, opts: &FetchOptions) -> Result<Document, Error> {
+        within(opts, opts.deadline(), async {
+            let (url, resp) = self.follow(url, opts, false).await?;
+            let (page, meta) = self.parse(resp).await?;
 
-        let protocol = self
-            .protocols
-            .get(scheme)
-            .or_else(|| {
-                self.protocols
-                    .get(self.protocols.keys().find(|p| glob_match(&p, scheme))?)
-            })
-            .ok_or(Error::UnsupportedProtocol)?;
+            Ok(Document { url, page, meta })
+        })
+        .await
+    }
 
-        let resp = protocol.fetch(&url).await?;
+    /// Process url and get dalet page while it arrives, following redirects.
+    /// Types without [`LineInput`] and pages in scope of page transformers,
+    /// which need whole page, are parsed after whole document is read
+    pub async fn process_stream(&self, url: &str) -> Result<DocumentStream, Error> {
+        self.process_stream_with(url, &self.options).await
+    }
 
-        let input = self
-            .inputs
-            .get(&resp.ty)
-            .or_else(|| {
-                self.inputs
-                    .get(self.inputs.keys().find(|p| glob_match(&p, &resp.ty))?)
-            })
-            .ok_or(Error::UnsupportedInput)?;
+    /// Same as [`Requester::process_stream`], but with own limits, timeouts and cancellation
+    pub async fn process_stream_with(
+        &self,
+        url: &str,
+        opts: &FetchOptions,
+    ) -> Result<DocumentStream, Error> {
+        let deadline = opts.deadline();
 
-        match resp.data {
-            TextOutput(s) => input.process_text(s, Some(&url)),
-            BitsOutput(b) => input.process_bytes(b, Some(&url)),
-        }
+        let (url, resp) = within(opts, deadline, self.follow(url, opts, true)).await?;
+
+        let transformed = !self
+            .transformers(resp.meta.url.as_ref(), &resp.ty)
+            .is_empty();
+
+        let source = match (resp.data, self.line_input(&resp.ty)) {
+            (ResponseData::Stream { body, text: true }, Ok(input)) if !transformed => {
+                Source::Lines {
+                    parser: input.parser(&resp.meta),
+                    body: BufReader::new(guard(body, opts, deadline)),
+                    ended: false,
+                }
+            }
+            (data, _) => {
+                let resp = Response { data, ..resp };
+                let (page, meta) = within(opts, deadline, self.parse(resp)).await?;
+
+                return Ok(DocumentStream {
+                    url,
+                    meta,
+                    source: Source::Page { page, taken: false },
+                });
+            }
+        };
+
+        Ok(DocumentStream {
+            url,
+            meta: resp.meta,
+            source,
+        })
+    }
+
+    /// Answer input prompt of url and get dalet page
+    pub async fn process_input(&self, url: &str, input: &str) -> Result<Document, Error> {
+        self.process_input_with(url, input, &self.options).await
     }
 
-    /// Process url and get response from protocol handler
+    /// Same as [`Requester::process_input`], but with own limits, timeouts and cancellation
+    pub async fn process_input_with(
+        &self,
+        url: &str,
+        input: &str,
+        opts: &FetchOptions,
+    ) -> Result<Document, Error> {
+        let mut url = Url::parse(url)?;
+
+        let query = utf8_percent_encode(input, QUERY_ENCODE_SET).to_string();
+        url.set_query(Some(&query));
+
+        self.process_with(url.as_str(), opts).await
+    }
+
+    /// Process url and get response from protocol handler.
+    /// Redirects are returned as is.
     pub async fn process_request(&self, url: &str) -> Result<Response, Error> {
+        self.process_request_with(url, &self.options).await
+    }
+
+    /// Same as [`Requester::process_request`], but with own limits, timeouts and cancellation
+    pub async fn process_request_with(
+        &self,
+        url: &str,
+        opts: &FetchOptions,
+    ) -> Result<Response, Error> {
         let url = Url::parse(url)?;
+        let protocol = self.protocol(url.scheme())?;
 
-        let scheme = url.scheme();
+        within(
+            opts,
+            opts.deadline(),
+            self.fetch(protocol, &url, opts, false),
+        )
+        .await
+    }
 
-        let protocol = self
-            .protocols
-            .get(scheme)
-            .or_else(|| {
-                self.protocols
-                    .get(self.protocols.keys().find(|p| glob_match(&p, scheme))?)
-            })
-            .ok_or(Error::UnsupportedProtocol)?;
+    /// Same as [`Requester::process_request`], but body may be streamed
+    pub async fn process_request_stream(&self, url: &str) -> Result<Response, Error> {
+        self.process_request_stream_with(url, &self.options).await
+    }
+
+    /// Same as [`Requester::process_request_stream`], but with own limits, timeouts and cancellation
+    pub async fn process_request_stream_with(
+        &self,
+        url: &str,
+        opts: &FetchOptions,
+    ) -> Result<Response, Error> {
+        let url = Url::parse(url)?;
+        let protocol = self.protocol(url.scheme())?;
+
+        let deadline = opts.deadline();
 
-        protocol.fetch(&url).await
+        let mut resp = within(opts, deadline, self.fetch(protocol, &url, opts, true)).await?;
+
+        if let ResponseData::Stream { body, text } = resp.data {
+            resp.data = ResponseData::Stream {
+                body: guard(body, opts, deadline),
+                text,
+            };
+        }
+
+        Ok(resp)
     }
 
     /// Process text with input type and get dalet page
     pub fn process_text(&self, input_type: &str, text: String) -> Result<Page, Error> {
-        self.inputs
+        let meta = Metadata::default();
+        let page = self
+            .inputs
             .get(input_type)
             .ok_or(Error::UnsupportedInput)?
-            .process_text(text, None)
+            .process_text(text, &meta)?;
+
+        transform(&self.transformers(None, input_type), page, &meta)
     }
 
     /// Process bytes with input type and get dalet page
     pub fn process_bytes(&self, input_type: &str, bytes: Vec<u8>) -> Result<Page, Error> {
-        self.inputs
+        let meta = Metadata::default();
+        let page = self
+            .inputs
             .get(input_type)
             .ok_or(Error::UnsupportedInput)?
-            .process_bytes(bytes, None)
+            .process_bytes(bytes, &meta)?;
+
+        transform(&self.transformers(None, input_type), page, &meta)
+    }
+
+    /// Render dalet page to output type. e.g. text/html
+    pub fn render(&self, page: &Page, output_type: &str) -> Result<Output, Error> {
+        self.output(output_type)?.render(page)
+    }
+
+    /// Process url and render its dalet page to output type
+    pub async fn convert(&self, url: &str, output_type: &str) -> Result<Output, Error> {
+        self.convert_with(url, output_type, &self.options).await
+    }
+
+    /// Same as [`Requester::convert`], but with own limits, timeouts and cancellation
+    pub async fn convert_with(
+        &self,
+        url: &str,
+        output_type: &str,
+        opts: &FetchOptions,
+    ) -> Result<Output, Error> {
+        let output = self.output(output_type)?;
+
+        output.render(&self.process_with(url, opts).await?.page)
+    }
+
+    /// Fetches url with protocol handler through middlewares, retrying if enabled
+    async fn fetch(
+        &self,
+        protocol: &Arc<dyn ProtocolHandler>,
+        url: &Url,
+        opts: &FetchOptions,
+        stream: bool,
+    ) -> Result<Response, Error> {
+        let mut attempt = 0;
+
+        loop {
+            let next = Next {
+                protocol: protocol.as_ref(),
+                chain: &self.middlewares,
+                stream,
+            };
+            let res = next.run(url, opts).await;
+
+            let delay = match (&res, &self.retry) {
+                (Err(e), Some(retry)) => retry.delay(e, attempt),
+                _ => None,
+            };
+
+            match delay {
+                Some(delay) => sleep(delay).await,
+                None => return res,
+            }
+
+            attempt += 1;
+        }
+    }
+
+    /// Fetches url following redirects, response data is never a redirect
+    async fn follow(
+        &self,
+        url: &str,
+        opts: &FetchOptions,
+        stream: bool,
+    ) -> Result<(Url, Response), Error> {
+        let mut url = Url::parse(url)?;
+        let mut visited: Vec<Url> = Vec::new();
+
+        loop {
+            let protocol = self.protocol(url.scheme())?;
+
+            let mut resp = self.fetch(protocol, &url, opts, stream).await?;
+
+            let ResponseData::Redirect { url: target, .. } = resp.data else {
+                resp.meta.url.get_or_insert_with(|| url.clone());
+
+                if let ResponseData::Stream { body, text } = resp.data {
+                    resp.data = ResponseData::Stream {
+                        body: body.max_size(opts.max_body_size),
+                        text,
+                    };
+                }
+
+                return Ok((url, resp));
+            };
+
+            if visited.len() >= self.max_redirects {
+                return Err(Error::ExceededMaxRedirects);
+            }
+
+            if target == url || visited.contains(&target) {
+                return Err(Error::RedirectLoop);
+            }
+
+            visited.push(std::mem::replace(&mut url, target));
+        }
+    }
+
+    /// Parses response with input handler, then runs page transformers
+    async fn parse(&self, resp: Response) -> Result<(Page, Metadata), Error> {
+        use ResponseData::*;
+
+        let input = self.input(&resp.ty)?.clone();
+        let transformers = self.transformers(resp.meta.url.as_ref(), &resp.ty);
+        let meta = resp.meta;
+
+        let data = match resp.data {
+            Stream { body, text } => body.buffer(text).await?,
+            data => data,
+        };
+
+        blocking(move || {
+            let page = match data {
+                TextOutput(s) => input.process_text(s, &meta)?,
+                BitsOutput(b) => input.process_bytes(b, &meta)?,
+                Stream { .. } | Redirect { .. } => return Err(Error::InvalidStatus),
+            };
+
+            Ok((transform(&transformers, page, &meta)?, meta))
+        })
+        .await
+    }
+
+    /// Transformers in scope of page, in order of registration
+    fn transformers(&self, url: Option<&Url>, ty: &str) -> Vec<Arc<dyn PageTransformer>> {
+        self.transformers
+            .iter()
+            .filter(|(scope, _)| scope.matches(url, ty))
+            .map(|(_, transformer)| transformer.clone())
+            .collect()
+    }
+
+    fn protocol(&self, scheme: &str) -> Result<&Arc<dyn ProtocolHandler>, Error> {
+        self.protocols
+            .get(scheme)
+            .or_else(|| {
+                self.protocols
+                    .get(self.protocols.keys().find(|p| glob_match(p, scheme))?)
+            })
+            .ok_or(Error::UnsupportedProtocol)
+    }
+
+    fn input(&self, ty: &str) -> Result<&Arc<dyn InputHandler>, Error> {
+        self.inputs
+            .get(ty)
+            .or_else(|| {
+                self.inputs
+                    .get(self.inputs.keys().find(|p| glob_match(p, ty))?)
+            })
+            .ok_or(Error::UnsupportedInput)
+    }
+
+    fn line_input(&self, ty: &str) -> Result<&Arc<dyn LineInput>, Error> {
+        self.line_inputs
+            .get(ty)
+            .or_else(|| {
+                self.line_inputs
+                    .get(self.line_inputs.keys().find(|p| glob_match(p, ty))?)
+            })
+            .ok_or(Error::UnsupportedInput)
+    }
+
+    fn output(&self, ty: &str) -> Result<&Arc<dyn OutputHandler>, Error> {
+        self.outputs
+            .get(ty)
+            .or_else(|| {
+                self.outputs
+                    .get(self.outputs.keys().find(|p| glob_match(p, ty))?)
+            })
+            .ok_or(Error::UnsupportedOutput)
     }
 }
 
-impl From<url::ParseError> for Error {
-    fn from(_: url::ParseError) -> Self {
-        Error::InvalidUrl
+/// Runs page through transformers in order
+fn transform(
+    transformers: &[Arc<dyn PageTransformer>],
+    page: Page,
+    meta: &Metadata,
+) -> Result<Page, Error> {
+    transformers
+        .iter()
+        .try_fold(page, |page, transformer| transformer.transform(page, meta))
+}
+
+/// Runs future until deadline or cancellation
+async fn within<T>(
+    opts: &FetchOptions,
+    deadline: Option<Instant>,
+    fut: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    let fut = async {
+        match deadline {
+            Some(deadline) => timeout_at(deadline, fut)
+                .await
+                .unwrap_or(Err(Error::Timeout)),
+            None => fut.await,
+        }
+    };
+
+    match &opts.cancel {
+        Some(cancel) => cancel
+            .run_until_cancelled(fut)
+            .await
+            .unwrap_or(Err(Error::Cancelled)),
+        None => fut.await,
     }
 }
 
-pub struct RequesterBuilder<'a> {
-    core: Requester<'a>,
+/// Applies limits, deadline and cancellation to streamed body
+fn guard(body: BodyStream, opts: &FetchOptions, deadline: Option<Instant>) -> BodyStream {
+    body.max_size(opts.max_body_size)
+        .deadline(deadline)
+        .cancel_on(opts.cancel.clone())
 }
 
-impl Default for RequesterBuilder<'_> {
-    fn default() -> Self {
-        Self {
-            core: Requester::default(),
+/// Runs conversion on blocking pool, so heavy inputs do not stall async runtime
+async fn blocking<T, F>(f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(res) => res,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(e) => Err(Error::IoError(e.to_string())),
+    }
+}
+
+impl From<io::Error> for Error {
+    /// Errors of requester carried by io error are unwrapped
+    fn from(e: io::Error) -> Self {
+        match e.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+            Some(e) => e.clone(),
+            None => Error::IoError(e.to_string()),
         }
     }
 }
 
-impl<'a> RequesterBuilder<'a> {
+impl From<url::ParseError> for Error {
+    fn from(_: url::ParseError) -> Self {
+        Error::InvalidUrl
+    }
+}
+
+#[derive(Default)]
+pub struct RequesterBuilder {
+    core: Requester,
+}
+
+impl RequesterBuilder {
     pub fn plugin<R>(self, registrar: R) -> Self
     where
         R: Fn(RequesterBuilder) -> RequesterBuilder,
@@ -188,17 +1087,92 @@ impl<'a> RequesterBuilder<'a> {
         registrar(self)
     }
 
-    pub fn protocol(mut self, schema: &'a str, protocol: &'a dyn ProtocolHandler) -> Self {
+    pub fn protocol(mut self, schema: &str, protocol: Arc<dyn ProtocolHandler>) -> Self {
         self.core.protocols.insert(schema.into(), protocol);
         self
     }
 
-    pub fn input(mut self, ty: &'a str, input: &'a dyn InputHandler) -> Self {
+    pub fn input(mut self, ty: &str, input: Arc<dyn InputHandler>) -> Self {
         self.core.inputs.insert(ty.into(), input);
         self
     }
 
-    pub fn build(self) -> Requester<'a> {
+    /// Input used by [`Requester::process_stream`] for streamed documents
+    pub fn line_input(mut self, ty: &str, input: Arc<dyn LineInput>) -> Self {
+        self.core.line_inputs.insert(ty.into(), input);
+        self
+    }
+
+    pub fn output(mut self, ty: &str, output: Arc<dyn OutputHandler>) -> Self {
+        self.core.outputs.insert(ty.into(), output);
+        self
+    }
+
+    /// Maximum number of redirects followed by [`Requester::process`]. Default is 5
+    pub fn max_redirects(mut self, max: usize) -> Self {
+        self.core.max_redirects = max;
+        self
+    }
+
+    /// Maximum size of body in bytes, checked while it is read
+    pub fn max_body_size(mut self, max: usize) -> Self {
+        self.core.options.max_body_size = Some(max);
+        self
+    }
+
+    /// Maximum size of headers or meta line in bytes
+    pub fn max_header_size(mut self, max: usize) -> Self {
+        self.core.options.max_header_size = Some(max);
+        self
+    }
+
+    /// Default timeout of connecting, including TLS handshake
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.core.options.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Default timeout of waiting for response after request was sent
+    pub fn first_byte_timeout(mut self, timeout: Duration) -> Self {
+        self.core.options.first_byte_timeout = Some(timeout);
+        self
+    }
+
+    /// Default timeout of whole request, including reading body
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.core.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Middleware around all protocol handlers.
+    /// First registered is outermost, each retry goes through whole chain
+    pub fn middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.core.middlewares.push(middleware);
+        self
+    }
+
+    /// Transformer run on every produced page, after ones registered before
+    pub fn transformer(self, transformer: Arc<dyn PageTransformer>) -> Self {
+        self.scoped_transformer(TransformScope::default(), transformer)
+    }
+
+    /// Transformer run on pages matching scope, after ones registered before
+    pub fn scoped_transformer(
+        mut self,
+        scope: TransformScope,
+        transformer: Arc<dyn PageTransformer>,
+    ) -> Self {
+        self.core.transformers.push((scope, transformer));
+        self
+    }
+
+    /// Retry failed requests, disabled by default
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.core.retry = Some(policy);
+        self
+    }
+
+    pub fn build(self) -> Requester {
         self.core
     }
 }
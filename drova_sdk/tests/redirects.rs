@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dalet::types::Page;
+use drova_sdk::requester::{
+    Error, FetchOptions, InputHandler, Metadata, ProtocolHandler, Requester, RequesterBuilder,
+    Response, ResponseData,
+};
+use url::Url;
+
+/// Redirects urls from table, other urls are text pages with url as body
+struct Redirects(Vec<(&'static str, &'static str)>);
+
+#[async_trait]
+impl ProtocolHandler for Redirects {
+    async fn fetch(&self, url: &Url, _: &FetchOptions) -> Result<Response, Error> {
+        let data = match self.0.iter().find(|(from, _)| *from == url.as_str()) {
+            Some((_, to)) => ResponseData::Redirect {
+                url: Url::parse(to).unwrap(),
+                permanent: false,
+            },
+            None => ResponseData::TextOutput(url.to_string()),
+        };
+
+        Ok(Response {
+            data,
+            ty: "text/plain".into(),
+            meta: Metadata::default(),
+        })
+    }
+}
+
+struct TitleInput;
+
+impl InputHandler for TitleInput {
+    fn process_text(&self, s: String, _: &Metadata) -> Result<Page, Error> {
+        Ok(Page {
+            title: Some(s),
+            description: None,
+            body: vec![],
+            variables: None,
+        })
+    }
+
+    fn process_bytes(&self, _: Vec<u8>, _: &Metadata) -> Result<Page, Error> {
+        Err(Error::UnsupportedInput)
+    }
+}
+
+fn requester(redirects: Vec<(&'static str, &'static str)>, max: usize) -> Requester {
+    let protocol = Arc::new(Redirects(redirects));
+
+    RequesterBuilder::default()
+        .protocol("one", protocol.clone())
+        .protocol("two", protocol)
+        .input("text/plain", Arc::new(TitleInput))
+        .max_redirects(max)
+        .build()
+}
+
+#[tokio::test]
+async fn follows_redirects_across_schemes() {
+    let requester = requester(vec![("one://a/", "two://b/"), ("two://b/", "one://c/")], 5);
+
+    let doc = requester.process("one://a/").await.unwrap();
+
+    assert_eq!(doc.url.as_str(), "one://c/");
+    assert_eq!(doc.page.title.as_deref(), Some("one://c/"));
+}
+
+#[tokio::test]
+async fn redirect_loop() {
+    let requester = requester(vec![("one://a/", "one://b/"), ("one://b/", "one://a/")], 5);
+
+    let res = requester.process("one://a/").await;
+
+    assert!(matches!(res, Err(Error::RedirectLoop)), "{res:?}");
+}
+
+#[tokio::test]
+async fn redirect_to_itself() {
+    let requester = requester(vec![("one://a/", "one://a/")], 5);
+
+    let res = requester.process("one://a/").await;
+
+    assert!(matches!(res, Err(Error::RedirectLoop)), "{res:?}");
+}
+
+#[tokio::test]
+async fn max_redirects() {
+    let chain = vec![
+        ("one://0/", "one://1/"),
+        ("one://1/", "two://2/"),
+        ("two://2/", "one://3/"),
+    ];
+
+    let doc = requester(chain.clone(), 3)
+        .process("one://0/")
+        .await
+        .unwrap();
+    assert_eq!(doc.url.as_str(), "one://3/");
+
+    let res = requester(chain, 2).process("one://0/").await;
+    assert!(matches!(res, Err(Error::ExceededMaxRedirects)), "{res:?}");
+}
+
+#[tokio::test]
+async fn redirects_are_returned_by_process_request() {
+    let requester = requester(vec![("one://a/", "one://b/")], 5);
+
+    let resp = requester.process_request("one://a/").await.unwrap();
+
+    assert!(matches!(
+        resp.data,
+        ResponseData::Redirect { url, .. } if url.as_str() == "one://b/"
+    ));
+}
@@ -0,0 +1,130 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use drova_sdk::requester::{
+    Error, FetchOptions, Metadata, ProtocolHandler, RequesterBuilder, Response, ResponseData,
+    RetryPolicy,
+};
+use url::Url;
+
+/// Fails with errors in order, then returns empty text page
+#[derive(Default)]
+struct Flaky {
+    errors: Mutex<Vec<Error>>,
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl ProtocolHandler for Flaky {
+    async fn fetch(&self, _: &Url, _: &FetchOptions) -> Result<Response, Error> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+
+        let mut errors = self.errors.lock().unwrap();
+
+        if !errors.is_empty() {
+            return Err(errors.remove(0));
+        }
+
+        Ok(Response {
+            data: ResponseData::TextOutput(String::new()),
+            ty: "text/plain".into(),
+            meta: Metadata::default(),
+        })
+    }
+}
+
+/// Number of calls of protocol and result of request
+async fn run(errors: Vec<Error>, retry: Option<RetryPolicy>) -> (usize, Result<Response, Error>) {
+    let flaky = Arc::new(Flaky {
+        errors: Mutex::new(errors),
+        ..Default::default()
+    });
+
+    let mut builder = RequesterBuilder::default().protocol("mock", flaky.clone());
+
+    if let Some(retry) = retry {
+        builder = builder.retry(retry);
+    }
+
+    let res = builder.build().process_request("mock://host/").await;
+
+    (flaky.calls.load(Ordering::SeqCst), res)
+}
+
+fn policy() -> RetryPolicy {
+    RetryPolicy {
+        max_retries: 2,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_secs(1),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn disabled_by_default() {
+    let (calls, res) = run(vec![Error::TemporalFailure], None).await;
+
+    assert_eq!(calls, 1);
+    assert!(matches!(res, Err(Error::TemporalFailure)), "{res:?}");
+}
+
+#[tokio::test]
+async fn retries_transient_errors() {
+    let (calls, res) = run(
+        vec![Error::TemporalFailure, Error::ServerUnavailable(None)],
+        Some(policy()),
+    )
+    .await;
+
+    assert_eq!(calls, 3);
+    assert!(res.is_ok(), "{res:?}");
+}
+
+#[tokio::test]
+async fn gives_up_after_max_retries() {
+    let (calls, res) = run(vec![Error::Timeout; 5], Some(policy())).await;
+
+    assert_eq!(calls, 3);
+    assert!(matches!(res, Err(Error::Timeout)), "{res:?}");
+}
+
+#[tokio::test]
+async fn does_not_retry_permanent_errors() {
+    let (calls, res) = run(vec![Error::NotFound], Some(policy())).await;
+
+    assert_eq!(calls, 1);
+    assert!(matches!(res, Err(Error::NotFound)), "{res:?}");
+}
+
+#[tokio::test]
+async fn honours_wait_time_within_max_delay() {
+    let (calls, res) = run(vec![Error::TooManyRequests(Some(0))], Some(policy())).await;
+
+    assert_eq!(calls, 2);
+    assert!(res.is_ok(), "{res:?}");
+}
+
+#[tokio::test]
+async fn does_not_retry_wait_longer_than_max_delay() {
+    let (calls, res) = run(vec![Error::TooManyRequests(Some(60))], Some(policy())).await;
+
+    assert_eq!(calls, 1);
+    assert!(
+        matches!(res, Err(Error::TooManyRequests(Some(60)))),
+        "{res:?}"
+    );
+
+    let (calls, res) = run(vec![Error::ServerUnavailable(Some(60))], Some(policy())).await;
+
+    assert_eq!(calls, 1);
+    assert!(
+        matches!(res, Err(Error::ServerUnavailable(Some(60)))),
+        "{res:?}"
+    );
+}